@@ -68,8 +68,7 @@ echo -e "\n${BLUE}Example 2: Regex Pattern Validation${NC}"
 cat > "${EXAMPLES_DIR}/regex-rule.json" << 'EOF'
 {
   "field_path": "$.user.email",
-  "validation_type": "regex",
-  "parameters": "^[a-zA-Z0-9._%+-]+@[a-zA-Z0-9.-]+\\.[a-zA-Z]{2,}$",
+  "validation_type": "regex:^[a-zA-Z0-9._%+-]+@[a-zA-Z0-9.-]+\\.[a-zA-Z]{2,}$",
   "description": "User email must be in valid format",
   "journey": "registration",
   "system": "user-portal"
@@ -91,9 +90,9 @@ echo -e "\n${BLUE}Example 3: Length Validation${NC}"
 cat > "${EXAMPLES_DIR}/length-rule.json" << 'EOF'
 {
   "field_path": "$.user.password",
-  "validation_type": "length",
-  "parameters": "min=8,max=64",
-  "description": "Password must be between 8 and 64 characters",
+  "validation_type": "min_length:8",
+  "parameters": {"message": "Password must be at least {limit} characters"},
+  "description": "Password must be at least 8 characters",
   "journey": "registration",
   "system": "user-portal"
 }
@@ -114,8 +113,7 @@ echo -e "\n${BLUE}Example 4: Minimum Value Validation${NC}"
 cat > "${EXAMPLES_DIR}/min-value-rule.json" << 'EOF'
 {
   "field_path": "$.user.age",
-  "validation_type": "min",
-  "parameters": "18",
+  "validation_type": "min_value:18",
   "description": "User must be at least 18 years old",
   "journey": "age_verification",
   "system": "ALL"
@@ -137,8 +135,8 @@ echo -e "\n${BLUE}Example 5: Maximum Value Validation${NC}"
 cat > "${EXAMPLES_DIR}/max-value-rule.json" << 'EOF'
 {
   "field_path": "$.payment.amount",
-  "validation_type": "max",
-  "parameters": "10000",
+  "validation_type": "max_value:10000",
+  "parameters": {"severity": "warning"},
   "description": "Payment amount cannot exceed 10000",
   "journey": "payment_processing",
   "system": "checkout"
@@ -160,8 +158,7 @@ echo -e "\n${BLUE}Example 6: Enum Validation${NC}"
 cat > "${EXAMPLES_DIR}/enum-rule.json" << 'EOF'
 {
   "field_path": "$.payment.currency",
-  "validation_type": "enum",
-  "parameters": "USD,EUR,GBP,JPY,CAD",
+  "validation_type": "in:[USD,EUR,GBP,JPY,CAD]",
   "description": "Currency must be one of the supported currencies",
   "journey": "payment_processing",
   "system": "checkout"
@@ -1,12 +1,15 @@
 use actix_web::{web, App, HttpResponse, HttpServer, Responder};
 
-use crate::models::{ApiResponse, NewRuleRequest, RuleDisplay, ValidationRequest};
+use crate::error::DqrError;
+use crate::models::{ApiResponse, BulkRuleRequest, NewRuleRequest, RuleUpdateRequest, ValidationOptions, ValidationRequest};
 use crate::validation::ValidationEngine;
 
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 
+// Handlers share the engine without locking; it manages its own concurrency
 pub struct ApiState {
-    validation_engine: Arc<Mutex<ValidationEngine>>,
+    validation_engine: Arc<ValidationEngine>,
 }
 
 pub async fn validate_json(
@@ -19,23 +22,12 @@ pub async fn validate_json(
     
     log::info!("Validating request for journey: {}, system: {}", journey, system);
     
-    // Get the validation engine from the state
-    let engine = match state.validation_engine.lock() {
-        Ok(engine) => engine,
-        Err(_) => {
-            log::error!("Failed to acquire lock on validation engine");
-            return HttpResponse::InternalServerError().json("Internal server error");
-        }
+    let engine = &state.validation_engine;
+    let options = ValidationOptions {
+        full_evaluation: req.full_evaluation,
     };
     
-    // Log the rules being applied
-    let rules = engine.get_rules_for_journey_system(journey, system);
-    for rule in &rules {
-        log::info!("Rule {} will be applied for {}:{}: [{}] {}", 
-                  rule.id, journey, system, rule.selector, rule.condition);
-    }
-    
-    match engine.validate(&req.data, journey, system) {
+    match engine.validate_with_options(&req.data, journey, system, &options) {
         Ok(response) => HttpResponse::Ok().json(response),
         Err(err) => {
             log::error!("Validation error: {}", err);
@@ -47,24 +39,21 @@ pub async fn validate_json(
 pub async fn health_check(state: web::Data<ApiState>) -> impl Responder {
     log::info!("Health check endpoint called");
     
-    // Get the validation engine from the state
-    let engine = match state.validation_engine.lock() {
-        Ok(engine) => engine,
-        Err(_) => {
-            log::error!("Failed to acquire lock on validation engine");
-            return HttpResponse::InternalServerError().json("Internal server error");
-        }
-    };
+    let engine = &state.validation_engine;
     
     // Get cache statistics
-    let validation_cache_size = engine.get_validation_cache_size();
-    let journey_system_cache_size = engine.get_journey_system_cache_size();
+    let validation_cache = engine.validation_cache_stats();
+    let journey_system_cache = engine.journey_system_cache_stats();
     
     HttpResponse::Ok().json(serde_json::json!({ 
         "status": "healthy",
         "cache_stats": {
-            "validation_cache_size": validation_cache_size,
-            "journey_system_cache_size": journey_system_cache_size
+            "validation_cache_size": validation_cache.size,
+            "validation_cache_hits": validation_cache.hits,
+            "validation_cache_misses": validation_cache.misses,
+            "journey_system_cache_size": journey_system_cache.size,
+            "journey_system_cache_hits": journey_system_cache.hits,
+            "journey_system_cache_misses": journey_system_cache.misses
         }
     }))
 }
@@ -73,18 +62,7 @@ pub async fn health_check(state: web::Data<ApiState>) -> impl Responder {
 pub async fn get_rules(state: web::Data<ApiState>) -> impl Responder {
     log::info!("Get rules endpoint called");
     
-    // Get the validation engine from the state
-    let engine = match state.validation_engine.lock() {
-        Ok(engine) => engine,
-        Err(_) => {
-            log::error!("Failed to acquire lock on validation engine");
-            return HttpResponse::InternalServerError().json(ApiResponse::<Vec<RuleDisplay>> {
-                success: false,
-                data: None,
-                error: Some("Internal server error".to_string()),
-            });
-        }
-    };
+    let engine = &state.validation_engine;
     
     let rules = engine.get_rules_for_display();
     
@@ -95,6 +73,42 @@ pub async fn get_rules(state: web::Data<ApiState>) -> impl Responder {
     })
 }
 
+// Error response for a failed rule edit: unknown rules are 404, IDs already in use 409,
+// failures to save the rules file 500 and invalid rules 400
+fn rule_error_response<T: serde::Serialize>(action: &str, err: DqrError) -> HttpResponse {
+    let body = ApiResponse::<T> {
+        success: false,
+        data: None,
+        error: Some(format!("Failed to {}: {}", action, err)),
+    };
+    match err {
+        DqrError::RuleNotFound(_) => HttpResponse::NotFound().json(body),
+        DqrError::DuplicateRuleId(_) | DqrError::RulesFileIncomplete(_) => HttpResponse::Conflict().json(body),
+        DqrError::IoError(_) | DqrError::CsvError(_) | DqrError::JsonError(_) | DqrError::YamlError(_) => {
+            HttpResponse::InternalServerError().json(body)
+        },
+        _ => HttpResponse::BadRequest().json(body),
+    }
+}
+
+// API endpoint to get a single rule
+pub async fn get_rule(
+    path: web::Path<String>,
+    state: web::Data<ApiState>,
+) -> impl Responder {
+    let rule_id = path.into_inner();
+    log::info!("Get rule endpoint called for rule ID: {}", rule_id);
+    
+    match state.validation_engine.get_rule(&rule_id) {
+        Some(rule) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            data: Some(rule),
+            error: None,
+        }),
+        None => rule_error_response::<()>("get rule", DqrError::RuleNotFound(rule_id)),
+    }
+}
+
 // API endpoint to create a new rule
 pub async fn create_rule(
     req: web::Json<NewRuleRequest>,
@@ -102,29 +116,11 @@ pub async fn create_rule(
 ) -> impl Responder {
     log::info!("Create rule endpoint called for {}", req.field_path);
     
-    // Get the validation engine from the state
-    let mut engine = match state.validation_engine.lock() {
-        Ok(engine) => engine,
-        Err(_) => {
-            log::error!("Failed to acquire lock on validation engine");
-            return HttpResponse::InternalServerError().json(ApiResponse::<String> {
-                success: false,
-                data: None,
-                error: Some("Internal server error".to_string()),
-            });
-        }
-    };
+    let engine = &state.validation_engine;
     
-    // Create the rule in memory first
+    // Create the rule; with a rules file it is saved before it takes effect
     match engine.create_rule(&req) {
         Ok(rule_id) => {
-            // Try to save rules to file, but don't fail the API call if this fails
-            if let Err(save_err) = engine.save_rules_to_file() {
-                // Log the error but continue
-                log::error!("Error saving rules to file: {}", save_err);
-            }
-            
-            // Return success even if saving to file failed, as the rule is in memory
             HttpResponse::Ok().json(ApiResponse {
                 success: true,
                 data: Some(rule_id),
@@ -133,12 +129,85 @@ pub async fn create_rule(
         }
         Err(err) => {
             log::error!("Error creating rule: {}", err);
-            HttpResponse::BadRequest().json(ApiResponse::<String> {
-                success: false,
-                data: None,
-                error: Some(format!("Failed to create rule: {}", err)),
+            rule_error_response::<String>("create rule", err)
+        }
+    }
+}
+
+// API endpoint to replace a rule, keeping its ID
+pub async fn update_rule(
+    path: web::Path<String>,
+    req: web::Json<NewRuleRequest>,
+    state: web::Data<ApiState>,
+) -> impl Responder {
+    let rule_id = path.into_inner();
+    log::info!("Update rule endpoint called for rule ID: {}", rule_id);
+    
+    let engine = &state.validation_engine;
+    
+    match engine.update_rule(&rule_id, &req) {
+        Ok(_) => {
+            HttpResponse::Ok().json(ApiResponse {
+                success: true,
+                data: Some(rule_id),
+                error: None,
             })
         }
+        Err(err) => {
+            log::error!("Error updating rule: {}", err);
+            rule_error_response::<String>("update rule", err)
+        }
+    }
+}
+
+// API endpoint to change some fields of a rule
+pub async fn patch_rule(
+    path: web::Path<String>,
+    req: web::Json<RuleUpdateRequest>,
+    state: web::Data<ApiState>,
+) -> impl Responder {
+    let rule_id = path.into_inner();
+    log::info!("Patch rule endpoint called for rule ID: {}", rule_id);
+    
+    let engine = &state.validation_engine;
+    
+    match engine.patch_rule(&rule_id, &req) {
+        Ok(_) => {
+            HttpResponse::Ok().json(ApiResponse {
+                success: true,
+                data: Some(rule_id),
+                error: None,
+            })
+        }
+        Err(err) => {
+            log::error!("Error patching rule: {}", err);
+            rule_error_response::<String>("update rule", err)
+        }
+    }
+}
+
+// API endpoint to apply several rule changes in one transaction
+pub async fn bulk_rules(
+    req: web::Json<BulkRuleRequest>,
+    state: web::Data<ApiState>,
+) -> impl Responder {
+    log::info!("Bulk rules endpoint called with {} operations", req.operations.len());
+    
+    let engine = &state.validation_engine;
+    
+    match engine.apply_bulk(&req.operations) {
+        Ok(created) => {
+            // The IDs of the created rules, in operation order
+            HttpResponse::Ok().json(ApiResponse {
+                success: true,
+                data: Some(created),
+                error: None,
+            })
+        }
+        Err(err) => {
+            log::error!("Error applying bulk rule changes, no rules changed: {}", err);
+            rule_error_response::<Vec<String>>("apply bulk rule changes", err)
+        }
     }
 }
 
@@ -150,28 +219,10 @@ pub async fn delete_rule(
     let rule_id = path.into_inner();
     log::info!("Delete rule endpoint called for rule ID: {}", rule_id);
     
-    // Get the validation engine from the state
-    let mut engine = match state.validation_engine.lock() {
-        Ok(engine) => engine,
-        Err(_) => {
-            log::error!("Failed to acquire lock on validation engine");
-            return HttpResponse::InternalServerError().json(ApiResponse::<()> {
-                success: false,
-                data: None,
-                error: Some("Internal server error".to_string()),
-            });
-        }
-    };
+    let engine = &state.validation_engine;
     
     match engine.delete_rule(&rule_id) {
         Ok(_) => {
-            // Try to save rules to file, but don't fail the API call if this fails
-            if let Err(save_err) = engine.save_rules_to_file() {
-                // Log the error but continue
-                log::error!("Error saving rules to file: {}", save_err);
-            }
-            
-            // Return success even if saving to file failed, as the rule is deleted in memory
             HttpResponse::Ok().json(ApiResponse::<()> {
                 success: true,
                 data: None,
@@ -180,24 +231,82 @@ pub async fn delete_rule(
         }
         Err(err) => {
             log::error!("Error deleting rule: {}", err);
-            HttpResponse::BadRequest().json(ApiResponse::<()> {
+            rule_error_response::<()>("delete rule", err)
+        }
+    }
+}
+
+// API endpoint to reload rules from the rules file
+pub async fn reload_rules(state: web::Data<ApiState>) -> impl Responder {
+    log::info!("Reload rules endpoint called");
+    
+    let engine = &state.validation_engine;
+    
+    match engine.reload_rules() {
+        Ok(issues) => {
+            // In lenient mode the skipped rules are reported back
+            HttpResponse::Ok().json(ApiResponse {
+                success: true,
+                data: Some(issues.iter().map(|issue| issue.to_string()).collect::<Vec<_>>()),
+                error: None,
+            })
+        }
+        Err(err) => {
+            log::error!("Error reloading rules, keeping current rules: {}", err);
+            HttpResponse::UnprocessableEntity().json(ApiResponse::<Vec<String>> {
                 success: false,
                 data: None,
-                error: Some(format!("Failed to delete rule: {}", err)),
+                error: Some(format!("Failed to reload rules: {}", err)),
             })
         }
     }
 }
 
+// Poll the rules file's modification time and reload it when it changes
+async fn watch_rules_file(engine: Arc<ValidationEngine>, interval: Duration) {
+    let Some(path) = engine.rules_file_path() else {
+        log::warn!("No rules file to watch");
+        return;
+    };
+    
+    let modified = |path: &std::path::Path| -> Option<SystemTime> {
+        std::fs::metadata(path).and_then(|m| m.modified()).ok()
+    };
+    
+    log::info!("Watching {} for changes every {:?}", path.display(), interval);
+    let mut last_modified = modified(&path);
+    let mut ticker = actix_web::rt::time::interval(interval);
+    
+    loop {
+        ticker.tick().await;
+        
+        let current = modified(&path);
+        if current.is_none() || current == last_modified {
+            continue;
+        }
+        last_modified = current;
+        
+        if let Err(err) = engine.reload_rules() {
+            log::error!("Error reloading rules from {}, keeping current rules: {}", path.display(), err);
+        }
+    }
+}
+
 pub async fn start_server(
     validation_engine: ValidationEngine,
     host: &str,
     port: u16,
+    rules_watch_interval: Option<Duration>,
 ) -> std::io::Result<()> {
     log::info!("Starting server at {}:{}", host, port);
     
-    // Wrap the validation engine in Arc<Mutex>
-    let engine = Arc::new(Mutex::new(validation_engine));
+    // Share the validation engine between workers
+    let engine = Arc::new(validation_engine);
+    
+    // Optionally reload the rules file whenever it changes on disk
+    if let Some(interval) = rules_watch_interval {
+        actix_web::rt::spawn(watch_rules_file(engine.clone(), interval));
+    }
     
     HttpServer::new(move || {
         App::new()
@@ -208,12 +317,17 @@ pub async fn start_server(
             .route("/api/validate", web::post().to(validate_json))
             .route("/api/rules", web::get().to(get_rules))
             .route("/api/rules", web::post().to(create_rule))
+            .route("/api/rules/reload", web::post().to(reload_rules))
+            .route("/api/rules/bulk", web::post().to(bulk_rules))
+            .route("/api/rules/{id}", web::get().to(get_rule))
+            .route("/api/rules/{id}", web::put().to(update_rule))
+            .route("/api/rules/{id}", web::patch().to(patch_rule))
             .route("/api/rules/{id}", web::delete().to(delete_rule))
             .route("/health", web::get().to(health_check))
             // Add CORS middleware for frontend
             .wrap(actix_web::middleware::DefaultHeaders::new()
                 .add(("Access-Control-Allow-Origin", "*"))
-                .add(("Access-Control-Allow-Methods", "GET, POST, DELETE, OPTIONS"))
+                .add(("Access-Control-Allow-Methods", "GET, POST, PUT, PATCH, DELETE, OPTIONS"))
                 .add(("Access-Control-Allow-Headers", "Content-Type"))
             )
     })
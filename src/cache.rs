@@ -0,0 +1,89 @@
+use std::hash::Hash;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use serde::Serialize;
+
+// Size and expiry settings shared by the engine's caches
+#[derive(Debug, Clone, Copy)]
+pub struct CacheConfig {
+    // Maximum number of entries per cache; least recently used entries are evicted first
+    pub capacity: u64,
+    // How long an entry stays valid after insertion (None means no expiry)
+    pub ttl: Option<Duration>,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        CacheConfig {
+            capacity: 10_000,
+            ttl: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, PartialEq, Eq)]
+pub struct CacheStats {
+    pub size: u64,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+// A bounded, concurrent cache that counts hits and misses
+pub struct BoundedCache<K, V> {
+    entries: moka::sync::Cache<K, V>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl<K, V> BoundedCache<K, V>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    pub fn new(config: CacheConfig) -> Self {
+        let mut builder = moka::sync::Cache::builder().max_capacity(config.capacity);
+        if let Some(ttl) = config.ttl {
+            builder = builder.time_to_live(ttl);
+        }
+
+        BoundedCache {
+            entries: builder.build(),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub fn get(&self, key: &K) -> Option<V> {
+        let value = self.entries.get(key);
+        let counter = if value.is_some() { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+        value
+    }
+
+    pub fn insert(&self, key: K, value: V) {
+        self.entries.insert(key, value);
+    }
+
+    pub fn clear(&self) {
+        self.entries.invalidate_all();
+    }
+
+    pub fn len(&self) -> u64 {
+        // Evictions and invalidations are applied lazily; settle them before counting
+        self.entries.run_pending_tasks();
+        self.entries.entry_count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            size: self.len(),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use serde_json_path::JsonPath;
+
+use crate::condition::Condition;
+use crate::dependency::DependencyExpr;
+use crate::error::DqrError;
+use crate::models::{Severity, ValidationRule};
+use crate::reference_lists::ReferenceLists;
+use crate::selector::Selector;
+
+// A rule with its selector, condition and dependency compiled ahead of time.
+// The source rule is kept for its metadata (ID, journey, logic type, parameters).
+#[derive(Debug)]
+pub struct CompiledRule {
+    pub rule: ValidationRule,
+    pub selector: Selector,
+    // Elements the rule is evaluated against one at a time
+    pub scope: Option<Selector>,
+    pub condition: Condition,
+    pub dependency: Option<DependencyExpr>,
+    // Trimmed entries of the comma-separated key_fields column
+    pub key_fields: Vec<String>,
+    pub severity: Severity,
+    pub message_template: Option<String>,
+    // Whether an "if" rule also reports its failures instead of only choosing a branch
+    pub report_errors: bool,
+}
+
+impl CompiledRule {
+    pub fn compile(rule: ValidationRule) -> Result<Self, DqrError> {
+        Self::compile_with_lists(rule, &ReferenceLists::default())
+    }
+
+    // Compile a rule whose condition may refer to named reference lists
+    pub fn compile_with_lists(rule: ValidationRule, lists: &ReferenceLists) -> Result<Self, DqrError> {
+        let with_rule_id = |e: DqrError| match e {
+            DqrError::InvalidRuleConfig(msg) => {
+                DqrError::InvalidRuleConfig(format!("rule '{}': {}", rule.id, msg))
+            },
+            other => other,
+        };
+
+        let selector = Selector::parse(&rule.selector).map_err(with_rule_id)?;
+        let scope = match rule.scope.trim() {
+            "" => None,
+            scope => Some(Selector::parse(scope).map_err(with_rule_id)?),
+        };
+        let condition = Condition::parse_with_lists(&rule.condition, lists).map_err(with_rule_id)?;
+
+        // A selector plus condition is a single dependency; a condition alone is an expression
+        let dependency = match (rule.depends_on_selector.trim(), rule.depends_on_condition.trim()) {
+            (_, "") => None,
+            // Before dependency expressions a condition without a selector was ignored; keep
+            // ignoring one that isn't an expression so existing rules files still load
+            ("", condition) if !DependencyExpr::is_expression(condition) => {
+                log::warn!(
+                    "Rule {}: depends_on_condition '{}' has no depends_on_selector and is ignored",
+                    rule.id, condition
+                );
+                None
+            },
+            ("", expression) => Some(DependencyExpr::parse(expression, lists).map_err(with_rule_id)?),
+            (selector, condition) => Some(DependencyExpr::single(selector, condition, lists).map_err(with_rule_id)?),
+        };
+
+        let key_fields = rule.key_fields
+            .split(',')
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .collect();
+
+        let severity = rule.parameters
+            .get("severity")
+            .and_then(|v| v.as_str())
+            .and_then(Severity::parse)
+            .unwrap_or_default();
+
+        let message_template = rule.parameters
+            .get("message")
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string());
+
+        let report_errors = match rule.parameters.get("report_errors") {
+            Some(serde_json::Value::Bool(report)) => *report,
+            Some(serde_json::Value::String(report)) => report.eq_ignore_ascii_case("true"),
+            _ => false,
+        };
+
+        Ok(CompiledRule {
+            rule,
+            selector,
+            scope,
+            condition,
+            dependency,
+            key_fields,
+            severity,
+            message_template,
+            report_errors,
+        })
+    }
+
+    // "if" rules act as predicates that only choose a branch, unless they opt in to reporting
+    pub fn reports_errors(&self) -> bool {
+        !self.rule.is_condition_root() || self.report_errors
+    }
+}
+
+pub fn parse_selector(selector: &str) -> Result<JsonPath, DqrError> {
+    JsonPath::parse(selector)
+        .map_err(|e| DqrError::InvalidRuleConfig(format!("invalid JSONPath '{}': {}", selector, e)))
+}
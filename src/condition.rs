@@ -0,0 +1,691 @@
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use regex::Regex;
+use serde_json::Value;
+use serde_json_path::JsonPath;
+
+use crate::compiled::parse_selector;
+use crate::dates::{age_in_years, validate_date_format, DateBound, DateValue};
+use crate::error::DqrError;
+use crate::expr::Expression;
+use crate::reference_lists::ReferenceLists;
+use crate::selector::Selector;
+
+// A validation condition parsed from its textual form (e.g. "min_length:2").
+// Parsing happens once when rules are loaded so the hot path never re-parses strings.
+#[derive(Debug, Clone)]
+pub enum Condition {
+    Required,
+    IsNumber,
+    IsString,
+    IsBoolean,
+    IsArray,
+    IsObject,
+    IsNull,
+    MinLength(usize),
+    MaxLength(usize),
+    MinValue(f64),
+    MaxValue(f64),
+    Equals(String),
+    Regex(Regex),
+    // Compare the value against another field in the same document
+    CompareField(Comparison, String, Selector),
+    // Sum every selected value and compare the total against another field
+    SumEqualsField(String, Selector),
+    // A date string in the given strftime format
+    IsDate(String),
+    // An RFC 3339 timestamp
+    IsDatetime,
+    // Dates strictly before/after a bound; the source text is kept for messages
+    DateBefore(String, DateBound),
+    DateAfter(String, DateBound),
+    // A date of birth implying an age of at least/at most N whole years
+    MinAge(i64),
+    MaxAge(i64),
+    // Membership in an inline or referenced list of allowed values
+    InList(AllowedValues),
+    // Array cardinality and contents
+    MinItems(usize),
+    MaxItems(usize),
+    UniqueItems,
+    // Items must be unique by the value at a path relative to each item
+    UniqueBy(String, JsonPath),
+    Contains(String),
+    // Boolean combinations of sub-conditions, reported as a single failure.
+    // Each sub-condition keeps its source text for messages.
+    AllOf(Vec<(String, Condition)>),
+    AnyOf(Vec<(String, Condition)>),
+    OneOf(Vec<(String, Condition)>),
+    Not(String, Box<Condition>),
+    // A boolean expression over the document, e.g. "expr:$.income * 12 >= $.loan.amount / 5"
+    Expr(Box<Expression>),
+    // Apply the inner condition only when a guard on another field holds,
+    // e.g. "min_length:2 when $.application.individuals.number == 1"
+    When(Box<Condition>, Guard),
+}
+
+#[derive(Debug, Clone)]
+pub struct Guard {
+    pub selector: Selector,
+    // None: the guard holds when the selected field is present and not empty
+    pub test: Option<(Comparison, Value)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AllowedValues {
+    // How the list was written, e.g. "[USD, EUR]" or "@currencies"
+    pub source: String,
+    // Lowercased when the comparison is case-insensitive
+    pub values: Arc<HashSet<String>>,
+    // The values that are numbers, so 1 matches 1.0
+    pub numbers: Vec<f64>,
+    pub case_insensitive: bool,
+    // not_in: the value must NOT be in the list
+    pub negated: bool,
+}
+
+// What a check can see besides the selected value
+#[derive(Debug, Clone, Copy)]
+pub struct CheckContext<'a> {
+    // The whole document, for conditions that look elsewhere
+    pub root: &'a Value,
+    // The element selected by the rule's scope that "@" selectors resolve against;
+    // the whole document when the rule has no scope
+    pub scope: &'a Value,
+    // The current time, for relative date conditions
+    pub now: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    LessThan,
+    LessOrEqual,
+    GreaterThan,
+    GreaterOrEqual,
+}
+
+// Details about a failed check, used when rendering the error message
+#[derive(Debug, Clone, Default)]
+pub struct Failure {
+    pub limit: Option<String>,
+}
+
+impl Condition {
+    pub fn parse(condition: &str) -> Result<Self, DqrError> {
+        Self::parse_with_lists(condition, &ReferenceLists::default())
+    }
+
+    // Parse a condition, resolving `in:@name` references against the loaded lists
+    pub fn parse_with_lists(condition: &str, lists: &ReferenceLists) -> Result<Self, DqrError> {
+        let condition = condition.trim();
+
+        // A trailing guard applies to the whole condition
+        if let Some((inner, guard)) = split_guard(condition) {
+            let inner = Self::parse_with_lists(inner, lists)?;
+            return Ok(Condition::When(Box::new(inner), Guard::parse(guard)?));
+        }
+
+        if condition == "min_length_when_single" {
+            return Err(DqrError::InvalidRuleConfig(
+                "condition 'min_length_when_single' was removed; use 'min_length:2 when $.application.individuals.number == 1'".to_string(),
+            ));
+        }
+
+        if let Some(composite) = Self::parse_composite(condition, lists)? {
+            return Ok(composite);
+        }
+
+        let parsed = match condition {
+            "required" => Condition::Required,
+            "is_number" => Condition::IsNumber,
+            "is_string" => Condition::IsString,
+            "is_boolean" => Condition::IsBoolean,
+            "is_array" => Condition::IsArray,
+            "is_object" => Condition::IsObject,
+            "is_null" => Condition::IsNull,
+            "is_date" => Condition::IsDate("%Y-%m-%d".to_string()),
+            "is_datetime" => Condition::IsDatetime,
+            "unique_items" => Condition::UniqueItems,
+            _ => {
+                let (name, argument) = condition.split_once(':').ok_or_else(|| {
+                    DqrError::InvalidRuleConfig(format!("unknown condition '{}'", condition))
+                })?;
+
+                match name {
+                    "min_length" => Condition::MinLength(parse_number(name, argument)?),
+                    "max_length" => Condition::MaxLength(parse_number(name, argument)?),
+                    "min_value" => Condition::MinValue(parse_number(name, argument)?),
+                    "max_value" => Condition::MaxValue(parse_number(name, argument)?),
+                    "equals" => Condition::Equals(argument.to_string()),
+                    "equals_field" => compare_field(Comparison::Equal, argument)?,
+                    "not_equals_field" => compare_field(Comparison::NotEqual, argument)?,
+                    "less_than_field" => compare_field(Comparison::LessThan, argument)?,
+                    "less_or_equal_field" => compare_field(Comparison::LessOrEqual, argument)?,
+                    "greater_than_field" => compare_field(Comparison::GreaterThan, argument)?,
+                    "greater_or_equal_field" => compare_field(Comparison::GreaterOrEqual, argument)?,
+                    "is_date" => {
+                        validate_date_format(argument)?;
+                        Condition::IsDate(argument.to_string())
+                    },
+                    "date_before" => Condition::DateBefore(argument.trim().to_string(), DateBound::parse(argument)?),
+                    "date_after" => Condition::DateAfter(argument.trim().to_string(), DateBound::parse(argument)?),
+                    "in" => Condition::InList(AllowedValues::parse(argument, lists, false, false)?),
+                    "not_in" => Condition::InList(AllowedValues::parse(argument, lists, false, true)?),
+                    "in_ci" => Condition::InList(AllowedValues::parse(argument, lists, true, false)?),
+                    "not_in_ci" => Condition::InList(AllowedValues::parse(argument, lists, true, true)?),
+                    "min_items" => Condition::MinItems(parse_number(name, argument)?),
+                    "max_items" => Condition::MaxItems(parse_number(name, argument)?),
+                    "unique_by" => {
+                        let argument = argument.trim();
+                        Condition::UniqueBy(argument.to_string(), parse_selector(argument)?)
+                    },
+                    "contains" => Condition::Contains(argument.to_string()),
+                    "expr" => Condition::Expr(Box::new(Expression::parse(argument)?)),
+                    "min_age" => Condition::MinAge(parse_number(name, argument)?),
+                    "max_age" => Condition::MaxAge(parse_number(name, argument)?),
+                    "sum_equals_field" => {
+                        let argument = argument.trim();
+                        Condition::SumEqualsField(argument.to_string(), Selector::parse(argument)?)
+                    },
+                    "regex" => Condition::Regex(Regex::new(argument).map_err(|e| {
+                        DqrError::InvalidRuleConfig(format!("invalid regex '{}': {}", argument, e))
+                    })?),
+                    _ => {
+                        return Err(DqrError::InvalidRuleConfig(format!("unknown condition '{}'", condition)));
+                    }
+                }
+            }
+        };
+
+        Ok(parsed)
+    }
+
+    // Parse "all_of(c1, c2, ...)", "any_of(...)", "one_of(...)" or "not(c)"
+    fn parse_composite(condition: &str, lists: &ReferenceLists) -> Result<Option<Self>, DqrError> {
+        let Some((name, rest)) = condition.split_once('(') else {
+            return Ok(None);
+        };
+        if !matches!(name.trim(), "all_of" | "any_of" | "one_of" | "not") {
+            return Ok(None);
+        }
+        let body = rest.strip_suffix(')').ok_or_else(|| {
+            DqrError::InvalidRuleConfig(format!("missing ')' in '{}'", condition))
+        })?;
+
+        let mut operands = Vec::new();
+        for part in split_top_level(body) {
+            let part = part.trim();
+            if part.is_empty() {
+                return Err(DqrError::InvalidRuleConfig(format!("empty sub-condition in '{}'", condition)));
+            }
+            let parsed = Self::parse_with_lists(part, lists)?;
+            if parsed.is_aggregate() {
+                return Err(DqrError::InvalidRuleConfig(format!(
+                    "'{}' checks the whole selection and can't be combined in '{}'", part, condition
+                )));
+            }
+            operands.push((part.to_string(), parsed));
+        }
+
+        let composite = match (name.trim(), operands.len()) {
+            ("not", 1) => {
+                let (source, inner) = operands.remove(0);
+                Condition::Not(source, Box::new(inner))
+            },
+            ("not", n) => {
+                return Err(DqrError::InvalidRuleConfig(format!("not() takes 1 condition but {} were given", n)));
+            },
+            ("all_of", _) => Condition::AllOf(operands),
+            ("any_of", _) => Condition::AnyOf(operands),
+            _ => Condition::OneOf(operands),
+        };
+        Ok(Some(composite))
+    }
+
+    // Whether an empty selection counts as a failure
+    pub fn requires_presence(&self, ctx: &CheckContext) -> bool {
+        match self {
+            Condition::Required => true,
+            Condition::When(inner, guard) => guard.holds(ctx) && inner.requires_presence(ctx),
+            Condition::AllOf(operands) => operands.iter().any(|(_, c)| c.requires_presence(ctx)),
+            Condition::AnyOf(operands) => operands.iter().all(|(_, c)| c.requires_presence(ctx)),
+            _ => false,
+        }
+    }
+
+    // Whether the condition checks the selection as a whole rather than each value
+    pub fn is_aggregate(&self) -> bool {
+        match self {
+            Condition::SumEqualsField(..) => true,
+            Condition::When(inner, _) => inner.is_aggregate(),
+            _ => false,
+        }
+    }
+
+    // Check the whole selection of an aggregate condition. Returns the aggregated
+    // value alongside any failure so it can be reported.
+    pub fn check_all(&self, values: &[&Value], ctx: &CheckContext) -> (Value, Option<Failure>) {
+        match self {
+            Condition::SumEqualsField(other_selector, other) => {
+                let limit = Some(describe_field(other_selector, other, ctx));
+
+                // A non-numeric item makes the sum meaningless
+                let Some(sum) = values.iter().map(|v| v.as_f64()).sum::<Option<f64>>() else {
+                    let items = values.iter().map(|v| (*v).clone()).collect();
+                    return (Value::Array(items), Some(Failure { limit }));
+                };
+
+                let expected = other.query(ctx).first().and_then(|v| v.as_f64());
+                let failed = expected.is_none_or(|total| !numbers_equal(sum, total));
+                (number_value(sum), failed.then_some(Failure { limit }))
+            },
+            Condition::When(inner, guard) if guard.holds(ctx) => inner.check_all(values, ctx),
+            _ => (Value::Null, None),
+        }
+    }
+
+    // Check a selected value
+    pub fn check(&self, value: &Value, ctx: &CheckContext) -> Option<Failure> {
+        // Each arm decides whether the value fails, plus the limit it was checked against
+        let (failed, limit) = match self {
+            Condition::Required => (value.is_null() || value.as_str() == Some(""), None),
+            Condition::IsNumber => (!value.is_number(), None),
+            Condition::IsString => (!value.is_string(), None),
+            Condition::IsBoolean => (!value.is_boolean(), None),
+            Condition::IsArray => (!value.is_array(), None),
+            Condition::IsObject => (!value.is_object(), None),
+            Condition::IsNull => (!value.is_null(), None),
+            Condition::MinLength(min_length) => (
+                value.as_str().is_none_or(|s| s.len() < *min_length),
+                Some(min_length.to_string()),
+            ),
+            Condition::MaxLength(max_length) => (
+                value.as_str().is_none_or(|s| s.len() > *max_length),
+                Some(max_length.to_string()),
+            ),
+            Condition::MinValue(min_value) => (
+                value.as_f64().is_none_or(|v| v < *min_value),
+                Some(min_value.to_string()),
+            ),
+            Condition::MaxValue(max_value) => (
+                value.as_f64().is_none_or(|v| v > *max_value),
+                Some(max_value.to_string()),
+            ),
+            Condition::Equals(expected_value) => (
+                !value_equals(value, expected_value),
+                Some(expected_value.clone()),
+            ),
+            Condition::Regex(regex) => (
+                value.as_str().is_none_or(|s| !regex.is_match(s)),
+                Some(regex.as_str().to_string()),
+            ),
+            Condition::CompareField(comparison, other_selector, other) => {
+                // A missing or incomparable field can't satisfy the comparison
+                let passed = other.query(ctx).first().is_some_and(|other_value| {
+                    comparison.holds(value, other_value)
+                });
+                (!passed, Some(describe_field(other_selector, other, ctx)))
+            },
+            Condition::IsDate(format) => (
+                value.as_str().is_none_or(|s| NaiveDate::parse_from_str(s, format).is_err()),
+                Some(format.clone()),
+            ),
+            Condition::IsDatetime => (
+                value.as_str().is_none_or(|s| DateTime::parse_from_rfc3339(s).is_err()),
+                None,
+            ),
+            Condition::DateBefore(text, bound) => check_date_bound(value, text, bound, ctx.now, Ordering::Less),
+            Condition::DateAfter(text, bound) => check_date_bound(value, text, bound, ctx.now, Ordering::Greater),
+            Condition::MinAge(min_age) => (
+                date_value(value).is_none_or(|dob| age_in_years(dob.date(), ctx.now.date_naive()) < *min_age),
+                Some(min_age.to_string()),
+            ),
+            Condition::MaxAge(max_age) => (
+                date_value(value).is_none_or(|dob| age_in_years(dob.date(), ctx.now.date_naive()) > *max_age),
+                Some(max_age.to_string()),
+            ),
+            Condition::InList(allowed) => (
+                !allowed.permits(value),
+                Some(allowed.source.clone()),
+            ),
+            Condition::MinItems(min_items) => (
+                value.as_array().is_none_or(|items| items.len() < *min_items),
+                Some(min_items.to_string()),
+            ),
+            Condition::MaxItems(max_items) => (
+                value.as_array().is_none_or(|items| items.len() > *max_items),
+                Some(max_items.to_string()),
+            ),
+            Condition::UniqueItems => match value.as_array() {
+                Some(items) => {
+                    let duplicate = first_duplicate(items.iter());
+                    (duplicate.is_some(), duplicate)
+                },
+                None => (true, None),
+            },
+            Condition::UniqueBy(key_selector, key) => match value.as_array() {
+                // Items without the key can't collide and are ignored
+                Some(items) => {
+                    let duplicate = first_duplicate(items.iter().filter_map(|item| key.query(item).first()));
+                    (duplicate.is_some(), duplicate.map(|d| format!("{} = {}", key_selector, d)))
+                },
+                None => (true, None),
+            },
+            Condition::Contains(expected_value) => (
+                value.as_array().is_none_or(|items| !items.iter().any(|item| value_equals(item, expected_value))),
+                Some(expected_value.clone()),
+            ),
+            Condition::AllOf(operands) => (
+                operands.iter().any(|(_, c)| c.check(value, ctx).is_some()),
+                Some(join_sources(operands)),
+            ),
+            Condition::AnyOf(operands) => (
+                operands.iter().all(|(_, c)| c.check(value, ctx).is_some()),
+                Some(join_sources(operands)),
+            ),
+            Condition::OneOf(operands) => (
+                operands.iter().filter(|(_, c)| c.check(value, ctx).is_none()).count() != 1,
+                Some(join_sources(operands)),
+            ),
+            Condition::Not(source, inner) => (
+                inner.check(value, ctx).is_none(),
+                Some(source.clone()),
+            ),
+            Condition::Expr(expression) => (
+                !expression.holds(value, ctx),
+                Some(expression.source().to_string()),
+            ),
+            Condition::When(inner, guard) => {
+                return if guard.holds(ctx) { inner.check(value, ctx) } else { None };
+            },
+            // Aggregate conditions are checked once per selection by check_all
+            Condition::SumEqualsField(..) => (false, None),
+        };
+
+        failed.then_some(Failure { limit })
+    }
+}
+
+impl Guard {
+    // Parse "<selector>", or "<selector> <op> <literal>" with op one of == != < <= > >=.
+    // Literals are JSON (1, true, "text") or bare text.
+    fn parse(guard: &str) -> Result<Self, DqrError> {
+        let guard = guard.trim();
+        let operators = [
+            (" == ", Comparison::Equal),
+            (" != ", Comparison::NotEqual),
+            (" <= ", Comparison::LessOrEqual),
+            (" >= ", Comparison::GreaterOrEqual),
+            (" < ", Comparison::LessThan),
+            (" > ", Comparison::GreaterThan),
+        ];
+
+        let operator = operators
+            .iter()
+            .filter_map(|(token, comparison)| guard.find(token).map(|pos| (pos, *token, *comparison)))
+            .min_by_key(|(pos, _, _)| *pos);
+
+        let Some((pos, token, comparison)) = operator else {
+            return Ok(Guard { selector: Selector::parse(guard)?, test: None });
+        };
+
+        let selector = Selector::parse(guard[..pos].trim())?;
+        let literal = guard[pos + token.len()..].trim();
+        if literal.is_empty() {
+            return Err(DqrError::InvalidRuleConfig(format!("missing value in guard '{}'", guard)));
+        }
+        let operand = serde_json::from_str(literal).unwrap_or_else(|_| Value::String(literal.to_string()));
+
+        Ok(Guard { selector, test: Some((comparison, operand)) })
+    }
+
+    // The guard holds when any selected value passes its test
+    pub fn holds(&self, ctx: &CheckContext) -> bool {
+        self.selector.query(ctx).iter().any(|value| match &self.test {
+            Some((comparison, operand)) => comparison.holds(value, operand),
+            None => is_not_empty(value),
+        })
+    }
+}
+
+impl Comparison {
+    // Whether `value <op> other` holds. Numbers compare numerically and strings
+    // lexicographically (so ISO 8601 dates order correctly); other combinations
+    // are only ever equal when identical.
+    pub fn holds(&self, value: &Value, other: &Value) -> bool {
+        let ordering = match (value, other) {
+            (Value::Number(a), Value::Number(b)) => match (a.as_f64(), b.as_f64()) {
+                (Some(a), Some(b)) if numbers_equal(a, b) => Some(Ordering::Equal),
+                (Some(a), Some(b)) => a.partial_cmp(&b),
+                _ => None,
+            },
+            (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+            _ if value == other => Some(Ordering::Equal),
+            _ => None,
+        };
+
+        match self {
+            Comparison::Equal => ordering == Some(Ordering::Equal),
+            Comparison::NotEqual => ordering != Some(Ordering::Equal),
+            Comparison::LessThan => ordering == Some(Ordering::Less),
+            Comparison::LessOrEqual => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+            Comparison::GreaterThan => ordering == Some(Ordering::Greater),
+            Comparison::GreaterOrEqual => matches!(ordering, Some(Ordering::Greater | Ordering::Equal)),
+        }
+    }
+}
+
+impl AllowedValues {
+    // Parse "[a, b, c]" or "@list_name"
+    fn parse(argument: &str, lists: &ReferenceLists, case_insensitive: bool, negated: bool) -> Result<Self, DqrError> {
+        let argument = argument.trim();
+
+        let (source, values) = if let Some(name) = argument.strip_prefix('@') {
+            let values = lists.get(name).ok_or_else(|| {
+                DqrError::InvalidRuleConfig(format!("unknown reference list '{}'", name))
+            })?;
+            (argument.to_string(), values)
+        } else {
+            let items = argument
+                .strip_prefix('[')
+                .and_then(|rest| rest.strip_suffix(']'))
+                .ok_or_else(|| {
+                    DqrError::InvalidRuleConfig(format!("invalid value list '{}' (expected [a,b,c] or @list_name)", argument))
+                })?;
+            let values: Vec<String> = items
+                .split(',')
+                .map(|item| item.trim().trim_matches('"').to_string())
+                .filter(|item| !item.is_empty())
+                .collect();
+            (format!("[{}]", values.join(", ")), Arc::new(values.into_iter().collect()))
+        };
+
+        let values = if case_insensitive {
+            Arc::new(values.iter().map(|v| v.to_lowercase()).collect())
+        } else {
+            values
+        };
+
+        let numbers = values.iter().filter_map(|v| v.parse::<f64>().ok()).filter(|n| n.is_finite()).collect();
+
+        Ok(AllowedValues { source, values, numbers, case_insensitive, negated })
+    }
+
+    // Numbers are compared numerically; strings and booleans by their textual form
+    pub fn permits(&self, value: &Value) -> bool {
+        let text = match value {
+            Value::String(s) => s.clone(),
+            Value::Number(n) => {
+                let listed = n.as_f64().is_some_and(|n| self.numbers.iter().any(|m| numbers_equal(n, *m)));
+                return listed != self.negated;
+            },
+            Value::Bool(b) => b.to_string(),
+            _ => return false,
+        };
+        let text = if self.case_insensitive { text.to_lowercase() } else { text };
+
+        self.values.contains(&text) != self.negated
+    }
+}
+
+// Split off a trailing " when <guard>" that isn't nested inside a composite condition.
+// Only a " when " followed by a guard selector ("$." "$[" "@." "@[") outside quoted
+// strings starts a guard, so "equals:pay when due" and expr string literals stay whole.
+// A quote inside a word (O'Brien) doesn't start a string, and a regex body is opaque:
+// its quotes and brackets don't count.
+pub fn split_guard(condition: &str) -> Option<(&str, &str)> {
+    let opaque = condition.trim_start().starts_with("regex:");
+    let mut depth = 0usize;
+    let mut quote = None;
+    let mut escaped = false;
+    let mut split = None;
+    let mut previous = ' ';
+    for (i, c) in condition.char_indices() {
+        let in_word = previous.is_alphanumeric();
+        previous = c;
+        if let Some(q) = quote {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                _ if c == q => quote = None,
+                _ => {},
+            }
+            continue;
+        }
+        match c {
+            '"' | '\'' if !opaque && !in_word => quote = Some(c),
+            '(' | '[' | '{' if !opaque => depth += 1,
+            ')' | ']' | '}' if !opaque => depth = depth.saturating_sub(1),
+            ' ' if depth == 0 && condition[i..].starts_with(" when ") => {
+                let guard = condition[i + " when ".len()..].trim_start();
+                if ["$.", "$[", "@.", "@["].iter().any(|start| guard.starts_with(start)) {
+                    split = Some(i);
+                }
+            },
+            _ => {},
+        }
+    }
+    split.map(|pos| (&condition[..pos], &condition[pos + " when ".len()..]))
+}
+
+// Split on commas that aren't inside brackets, e.g. "in:[a,b], min_length:2"
+fn split_top_level(text: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    for (i, c) in text.char_indices() {
+        match c {
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth = depth.saturating_sub(1),
+            ',' if depth == 0 => {
+                parts.push(&text[start..i]);
+                start = i + 1;
+            },
+            _ => {},
+        }
+    }
+    parts.push(&text[start..]);
+    parts
+}
+
+fn join_sources(operands: &[(String, Condition)]) -> String {
+    operands.iter().map(|(source, _)| source.as_str()).collect::<Vec<_>>().join(", ")
+}
+
+// The first value seen twice, rendered for the error message
+fn first_duplicate<'a>(values: impl Iterator<Item = &'a Value>) -> Option<String> {
+    let mut seen = HashSet::new();
+    values
+        .map(|value| value.to_string())
+        .find(|key| !seen.insert(key.clone()))
+}
+
+fn date_value(value: &Value) -> Option<DateValue> {
+    value.as_str().and_then(DateValue::parse)
+}
+
+// The value must be a date ordered `expected` relative to the resolved bound
+fn check_date_bound(
+    value: &Value,
+    text: &str,
+    bound: &DateBound,
+    now: DateTime<Utc>,
+    expected: Ordering,
+) -> (bool, Option<String>) {
+    let resolved = bound.resolve(now);
+    let passed = match (date_value(value), resolved) {
+        (Some(date), Some(bound)) => date.compare(&bound) == expected,
+        _ => false,
+    };
+
+    // Show what a relative bound resolved to, e.g. "now-18y (2006-10-17)"
+    let limit = match (bound, resolved) {
+        (DateBound::Relative(..), Some(resolved)) => format!("{} ({})", text, resolved),
+        _ => text.to_string(),
+    };
+    (!passed, Some(limit))
+}
+
+fn compare_field(comparison: Comparison, argument: &str) -> Result<Condition, DqrError> {
+    let argument = argument.trim();
+    Ok(Condition::CompareField(comparison, argument.to_string(), Selector::parse(argument)?))
+}
+
+// Describe a referenced field for error messages, e.g. "$.end_date (2024-01-31)"
+fn describe_field(selector: &str, path: &Selector, ctx: &CheckContext) -> String {
+    match path.query(ctx).first() {
+        Some(Value::String(s)) => format!("{} ({})", selector, s),
+        Some(value) => format!("{} ({})", selector, value),
+        None => format!("{} (missing)", selector),
+    }
+}
+
+// Whole numbers are reported without a trailing ".0"
+fn number_value(n: f64) -> Value {
+    if n.fract() == 0.0 && n.abs() < i64::MAX as f64 {
+        Value::from(n as i64)
+    } else {
+        Value::from(n)
+    }
+}
+
+// Sums of decimal amounts rarely add up exactly in floating point
+fn numbers_equal(a: f64, b: f64) -> bool {
+    (a - b).abs() <= 1e-9 * a.abs().max(b.abs()).max(1.0)
+}
+
+fn parse_number<T: std::str::FromStr>(name: &str, argument: &str) -> Result<T, DqrError> {
+    argument.trim().parse::<T>().map_err(|_| {
+        DqrError::InvalidRuleConfig(format!("invalid numeric limit '{}' for {}", argument, name))
+    })
+}
+
+// Compare a JSON value against the textual operand of an equals condition
+pub fn value_equals(value: &Value, expected_value: &str) -> bool {
+    match value {
+        Value::String(s) => s == expected_value,
+        Value::Number(n) => match (expected_value.parse::<f64>(), n.as_f64()) {
+            (Ok(num), Some(val_num)) => (val_num - num).abs() < f64::EPSILON,
+            _ => false,
+        },
+        Value::Bool(b) => expected_value.parse::<bool>() == Ok(*b),
+        _ => false,
+    }
+}
+
+// Null, empty strings, empty arrays and empty objects are considered empty;
+// numbers and booleans never are
+pub fn is_not_empty(value: &Value) -> bool {
+    match value {
+        Value::String(s) => !s.is_empty(),
+        Value::Array(arr) => !arr.is_empty(),
+        Value::Object(obj) => !obj.is_empty(),
+        Value::Number(_) | Value::Bool(_) => true,
+        Value::Null => false,
+    }
+}
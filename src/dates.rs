@@ -0,0 +1,172 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Days, Months, NaiveDate, Utc};
+
+use crate::error::DqrError;
+
+// Source of the current time for relative date conditions; tests inject a fixed clock
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct FixedClock(pub DateTime<Utc>);
+
+impl Clock for FixedClock {
+    fn now(&self) -> DateTime<Utc> {
+        self.0
+    }
+}
+
+// A date or timestamp read from a document. Plain dates are compared at day precision.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateValue {
+    Date(NaiveDate),
+    DateTime(DateTime<Utc>),
+}
+
+impl DateValue {
+    // Accepts "YYYY-MM-DD" dates and RFC 3339 timestamps
+    pub fn parse(s: &str) -> Option<Self> {
+        if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+            return Some(DateValue::Date(date));
+        }
+        DateTime::parse_from_rfc3339(s)
+            .ok()
+            .map(|dt| DateValue::DateTime(dt.with_timezone(&Utc)))
+    }
+
+    pub fn date(&self) -> NaiveDate {
+        match self {
+            DateValue::Date(date) => *date,
+            DateValue::DateTime(dt) => dt.date_naive(),
+        }
+    }
+
+    // Compare against a resolved bound; a plain date on either side compares by day
+    pub fn compare(&self, bound: &DateValue) -> Ordering {
+        match (self, bound) {
+            (DateValue::DateTime(a), DateValue::DateTime(b)) => a.cmp(b),
+            _ => self.date().cmp(&bound.date()),
+        }
+    }
+}
+
+impl fmt::Display for DateValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DateValue::Date(date) => write!(f, "{}", date.format("%Y-%m-%d")),
+            DateValue::DateTime(dt) => write!(f, "{}", dt.to_rfc3339()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateUnit {
+    Years,
+    Months,
+    Weeks,
+    Days,
+}
+
+// The bound of a date_before/date_after condition: a fixed date or an offset from now
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DateBound {
+    Absolute(DateValue),
+    // "now", "now-18y", "now+30d"; the offset is signed
+    Relative(i64, DateUnit),
+}
+
+impl DateBound {
+    pub fn parse(bound: &str) -> Result<Self, DqrError> {
+        let bound = bound.trim();
+        let invalid = || DqrError::InvalidRuleConfig(format!(
+            "invalid date bound '{}' (expected YYYY-MM-DD, an RFC 3339 timestamp or now[+-]N[y|m|w|d])",
+            bound
+        ));
+
+        let Some(offset) = bound.strip_prefix("now") else {
+            return DateValue::parse(bound).map(DateBound::Absolute).ok_or_else(invalid);
+        };
+        if offset.is_empty() {
+            return Ok(DateBound::Relative(0, DateUnit::Days));
+        }
+
+        let (sign, offset) = match offset.split_at(1) {
+            ("+", rest) => (1, rest),
+            ("-", rest) => (-1, rest),
+            _ => return Err(invalid()),
+        };
+        let (amount, unit) = offset.split_at(offset.len().saturating_sub(1));
+        let unit = match unit {
+            "y" => DateUnit::Years,
+            "m" => DateUnit::Months,
+            "w" => DateUnit::Weeks,
+            "d" => DateUnit::Days,
+            _ => return Err(invalid()),
+        };
+        let amount: i64 = amount.parse().map_err(|_| invalid())?;
+
+        Ok(DateBound::Relative(sign * amount, unit))
+    }
+
+    // Resolve the bound against the current time. Relative bounds resolve to a date,
+    // so results only change once a day.
+    pub fn resolve(&self, now: DateTime<Utc>) -> Option<DateValue> {
+        match self {
+            DateBound::Absolute(value) => Some(*value),
+            DateBound::Relative(amount, unit) => {
+                shift_date(now.date_naive(), *amount, *unit).map(DateValue::Date)
+            },
+        }
+    }
+}
+
+// Move a date by a signed number of units; month arithmetic clamps to the end of the month
+pub fn shift_date(date: NaiveDate, amount: i64, unit: DateUnit) -> Option<NaiveDate> {
+    let (count, months) = match unit {
+        DateUnit::Years => (amount.checked_mul(12)?, true),
+        DateUnit::Months => (amount, true),
+        DateUnit::Weeks => (amount.checked_mul(7)?, false),
+        DateUnit::Days => (amount, false),
+    };
+    let magnitude = count.unsigned_abs();
+
+    if months {
+        let months = Months::new(u32::try_from(magnitude).ok()?);
+        if count < 0 { date.checked_sub_months(months) } else { date.checked_add_months(months) }
+    } else {
+        let days = Days::new(magnitude);
+        if count < 0 { date.checked_sub_days(days) } else { date.checked_add_days(days) }
+    }
+}
+
+// Full years elapsed between a date of birth and today
+pub fn age_in_years(date_of_birth: NaiveDate, today: NaiveDate) -> i64 {
+    use chrono::Datelike;
+
+    let mut age = i64::from(today.year() - date_of_birth.year());
+    if (today.month(), today.day()) < (date_of_birth.month(), date_of_birth.day()) {
+        age -= 1;
+    }
+    age
+}
+
+// Reject strftime formats chrono can't parse with, so typos surface at load time
+pub fn validate_date_format(format: &str) -> Result<(), DqrError> {
+    if format.is_empty() || StrftimeItems::new(format).any(|item| matches!(item, Item::Error)) {
+        return Err(DqrError::InvalidRuleConfig(format!("invalid date format '{}'", format)));
+    }
+    Ok(())
+}
@@ -0,0 +1,291 @@
+use serde_json::Value;
+
+use crate::condition::{is_not_empty, CheckContext, Condition};
+use crate::error::DqrError;
+use crate::reference_lists::ReferenceLists;
+use crate::selector::Selector;
+
+// A boolean combination of dependencies that must hold before a rule is evaluated, e.g.
+// "$.employment.status equals:employed AND ($.address.country equals:US OR NOT $.visa missing)"
+#[derive(Debug)]
+pub enum DependencyExpr {
+    Check(Dependency),
+    And(Vec<DependencyExpr>),
+    Or(Vec<DependencyExpr>),
+    Not(Box<DependencyExpr>),
+}
+
+#[derive(Debug)]
+pub struct Dependency {
+    pub selector: Selector,
+    pub quantifier: Quantifier,
+    pub condition: DependencyCondition,
+}
+
+// How many selected values must satisfy a dependency condition
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Quantifier {
+    #[default]
+    Any,
+    All,
+}
+
+#[derive(Debug, Clone)]
+pub enum DependencyCondition {
+    // The selector matches at least one value
+    Exists,
+    // The selector matches nothing
+    Missing,
+    NotEmpty,
+    // Any condition accepted in the main condition column
+    Value(Condition),
+}
+
+impl Dependency {
+    // Parse a selector and a condition optionally prefixed with a quantifier ("all:min_value:18")
+    pub fn parse(selector: &str, condition: &str, lists: &ReferenceLists) -> Result<Self, DqrError> {
+        let condition = condition.trim();
+        let (quantifier, condition) = if let Some(rest) = condition.strip_prefix("all:") {
+            (Quantifier::All, rest)
+        } else if let Some(rest) = condition.strip_prefix("any:") {
+            (Quantifier::Any, rest)
+        } else {
+            (Quantifier::Any, condition)
+        };
+
+        Ok(Dependency {
+            selector: Selector::parse(selector)?,
+            quantifier,
+            condition: DependencyCondition::parse(condition, lists)?,
+        })
+    }
+
+    // Whether the rule depending on this should be evaluated. Value conditions need
+    // any (or all) selected values to pass; an empty selection never meets them.
+    pub fn is_met(&self, ctx: &CheckContext) -> bool {
+        let selection = self.selector.query(ctx);
+
+        match &self.condition {
+            DependencyCondition::Exists => !selection.is_empty(),
+            DependencyCondition::Missing => selection.is_empty(),
+            condition => {
+                let mut values = selection.iter();
+                match self.quantifier {
+                    Quantifier::Any => values.any(|value| condition.matches(value, ctx)),
+                    Quantifier::All => !selection.is_empty() && values.all(|value| condition.matches(value, ctx)),
+                }
+            },
+        }
+    }
+}
+
+impl DependencyCondition {
+    pub fn parse(condition: &str, lists: &ReferenceLists) -> Result<Self, DqrError> {
+        match condition.trim() {
+            "exists" => Ok(DependencyCondition::Exists),
+            "missing" => Ok(DependencyCondition::Missing),
+            "not_empty" => Ok(DependencyCondition::NotEmpty),
+            other => Condition::parse_with_lists(other, lists)
+                .map(DependencyCondition::Value)
+                .map_err(|e| match e {
+                    DqrError::InvalidRuleConfig(msg) => {
+                        DqrError::InvalidRuleConfig(format!("invalid dependency condition: {}", msg))
+                    },
+                    other => other,
+                }),
+        }
+    }
+
+    pub fn matches(&self, value: &Value, ctx: &CheckContext) -> bool {
+        match self {
+            DependencyCondition::Exists => true,
+            DependencyCondition::Missing => false,
+            DependencyCondition::NotEmpty => is_not_empty(value),
+            DependencyCondition::Value(condition) => condition.check(value, ctx).is_none(),
+        }
+    }
+}
+
+impl DependencyExpr {
+    // A single selector/condition pair from the depends_on_selector and depends_on_condition columns
+    pub fn single(selector: &str, condition: &str, lists: &ReferenceLists) -> Result<Self, DqrError> {
+        Dependency::parse(selector, condition, lists).map(DependencyExpr::Check)
+    }
+
+    // Parse an expression of "SELECTOR CONDITION" checks joined by AND, OR and NOT
+    // (uppercase, binding in that order: NOT tightest) with parentheses for grouping
+    pub fn parse(expression: &str, lists: &ReferenceLists) -> Result<Self, DqrError> {
+        let mut parser = ExprParser { input: expression, pos: 0, lists };
+        let parsed = parser.parse_or().and_then(|expr| {
+            parser.skip_whitespace();
+            match parser.rest() {
+                "" => Ok(expr),
+                rest => Err(format!("unexpected '{}'", rest)),
+            }
+        });
+
+        parsed.map_err(|msg| {
+            DqrError::InvalidRuleConfig(format!("invalid dependency expression '{}': {}", expression, msg))
+        })
+    }
+
+    // Whether a depends_on_condition written without a depends_on_selector is an
+    // expression: it starts with a selector, a parenthesis or NOT
+    pub fn is_expression(text: &str) -> bool {
+        let text = text.trim_start();
+        text.starts_with(['$', '@', '(']) || starts_with_keyword(text, "NOT")
+    }
+
+    pub fn is_met(&self, ctx: &CheckContext) -> bool {
+        match self {
+            DependencyExpr::Check(dependency) => dependency.is_met(ctx),
+            DependencyExpr::And(operands) => operands.iter().all(|expr| expr.is_met(ctx)),
+            DependencyExpr::Or(operands) => operands.iter().any(|expr| expr.is_met(ctx)),
+            DependencyExpr::Not(operand) => !operand.is_met(ctx),
+        }
+    }
+}
+
+// Recursive descent parser over the expression text. Errors are plain messages that
+// DependencyExpr::parse wraps with the full expression.
+struct ExprParser<'a> {
+    input: &'a str,
+    pos: usize,
+    lists: &'a ReferenceLists,
+}
+
+impl<'a> ExprParser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.input[self.pos..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        self.skip_whitespace();
+        if starts_with_keyword(self.rest(), keyword) {
+            self.pos += keyword.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<DependencyExpr, String> {
+        let mut operands = vec![self.parse_and()?];
+        while self.eat_keyword("OR") {
+            operands.push(self.parse_and()?);
+        }
+        Ok(if operands.len() == 1 { operands.remove(0) } else { DependencyExpr::Or(operands) })
+    }
+
+    fn parse_and(&mut self) -> Result<DependencyExpr, String> {
+        let mut operands = vec![self.parse_unary()?];
+        while self.eat_keyword("AND") {
+            operands.push(self.parse_unary()?);
+        }
+        Ok(if operands.len() == 1 { operands.remove(0) } else { DependencyExpr::And(operands) })
+    }
+
+    fn parse_unary(&mut self) -> Result<DependencyExpr, String> {
+        if self.eat_keyword("NOT") {
+            return Ok(DependencyExpr::Not(Box::new(self.parse_unary()?)));
+        }
+
+        self.skip_whitespace();
+        if let Some(rest) = self.rest().strip_prefix('(') {
+            self.pos = self.input.len() - rest.len();
+            let expr = self.parse_or()?;
+            self.skip_whitespace();
+            return match self.rest().strip_prefix(')') {
+                Some(rest) => {
+                    self.pos = self.input.len() - rest.len();
+                    Ok(expr)
+                },
+                None => Err("expected ')'".to_string()),
+            };
+        }
+
+        self.parse_check()
+    }
+
+    // "SELECTOR CONDITION": the selector runs to the first whitespace outside brackets and
+    // quotes, so filters like $.a[?@.b == 1] are fine; the condition runs to the next AND/OR
+    // or unmatched ')', so brackets inside it (regex groups, in-lists) are fine
+    fn parse_check(&mut self) -> Result<DependencyExpr, String> {
+        let rest = self.rest();
+        if !rest.starts_with(['$', '@']) {
+            return Err(match rest {
+                "" => "unexpected end of expression".to_string(),
+                _ => format!("expected a JSONPath selector at '{}'", rest),
+            });
+        }
+
+        let selector_len = selector_length(rest);
+        let selector = &rest[..selector_len];
+        self.pos += selector_len;
+
+        let start = self.pos;
+        let mut depth = 0usize;
+        let mut end = self.input.len();
+        for (offset, c) in self.rest().char_indices() {
+            match c {
+                '(' | '[' | '{' => depth += 1,
+                ')' if depth == 0 => {
+                    end = start + offset;
+                    break;
+                },
+                ')' | ']' | '}' => depth = depth.saturating_sub(1),
+                c if c.is_whitespace() && depth == 0 => {
+                    let next = self.input[start + offset..].trim_start();
+                    if starts_with_keyword(next, "AND") || starts_with_keyword(next, "OR") {
+                        end = start + offset;
+                        break;
+                    }
+                },
+                _ => {},
+            }
+        }
+        self.pos = end;
+
+        let condition = self.input[start..end].trim();
+        if condition.is_empty() {
+            return Err(format!("missing condition for '{}'", selector));
+        }
+
+        Dependency::parse(selector, condition, self.lists)
+            .map(DependencyExpr::Check)
+            .map_err(|e| match e {
+                DqrError::InvalidRuleConfig(msg) => msg,
+                other => other.to_string(),
+            })
+    }
+}
+
+// Length of the selector at the start of text: up to the first whitespace that isn't
+// inside brackets or a quoted name
+fn selector_length(text: &str) -> usize {
+    let mut depth = 0usize;
+    let mut quote = None;
+    for (i, c) in text.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {},
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '[' | '(') => depth += 1,
+            (None, ']' | ')') => depth = depth.saturating_sub(1),
+            (None, c) if c.is_whitespace() && depth == 0 => return i,
+            _ => {},
+        }
+    }
+    text.len()
+}
+
+// Whether text starts with the keyword followed by whitespace, a parenthesis or the end
+fn starts_with_keyword(text: &str, keyword: &str) -> bool {
+    text.strip_prefix(keyword)
+        .is_some_and(|after| after.is_empty() || after.starts_with(|c: char| c.is_whitespace() || c == '('))
+}
@@ -8,15 +8,24 @@ pub enum DqrError {
     #[error("CSV parsing error: {0}")]
     CsvError(#[from] csv::Error),
 
+    #[error("YAML parsing error: {0}")]
+    YamlError(#[from] serde_yaml::Error),
+
     #[error("IO error: {0}")]
     IoError(#[from] std::io::Error),
 
     #[error("Validation rule not found: {0}")]
     RuleNotFound(String),
 
+    #[error("Rule ID already in use: {0}")]
+    DuplicateRuleId(String),
+
     #[error("Invalid rule configuration: {0}")]
     InvalidRuleConfig(String),
 
+    #[error("Rules file can't be saved: {0}")]
+    RulesFileIncomplete(String),
+
     #[error("JSON path error: {0}")]
     JsonPathError(String),
 
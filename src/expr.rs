@@ -0,0 +1,762 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+use serde_json::Value;
+use serde_json_path::JsonPath;
+
+use crate::compiled::parse_selector;
+use crate::condition::CheckContext;
+use crate::dates::DateValue;
+use crate::error::DqrError;
+
+// A small, side-effect free expression language for `expr:` conditions, e.g.
+// "$.income * 12 >= $.loan.amount / 5". Expressions are parsed and type-checked
+// when rules are loaded; evaluation only reads the document and the clock.
+#[derive(Debug, Clone)]
+pub struct Expression {
+    source: String,
+    root: Expr,
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Literal(Val),
+    // "$..." is resolved against the document and "@..." against the element of the rule's
+    // scope, as in every other selector. A singular path (only names and indices) yields
+    // one value; any other path a list.
+    Path { path: JsonPath, relative: bool, singular: bool },
+    // `value`: the value selected by the rule's selector
+    Value,
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    Call(Function, Vec<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum UnaryOp {
+    Neg,
+    Not,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    And,
+    Or,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Function {
+    Len,
+    Sum,
+    Abs,
+    Lower,
+    Upper,
+    Trim,
+    Contains,
+    StartsWith,
+    EndsWith,
+    Now,
+    Date,
+    DaysBetween,
+}
+
+// Runtime values. JSON arrays and multi-valued paths become lists.
+#[derive(Debug, Clone, PartialEq)]
+enum Val {
+    Null,
+    Bool(bool),
+    Num(f64),
+    Str(String),
+    Date(DateValue),
+    List(Vec<Val>),
+}
+
+// Types known at load time; values read from the document are `Any` until evaluated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Type {
+    Null,
+    Bool,
+    Num,
+    Str,
+    Date,
+    List,
+    Any,
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Type::Null => "null",
+            Type::Bool => "boolean",
+            Type::Num => "number",
+            Type::Str => "string",
+            Type::Date => "date",
+            Type::List => "list",
+            Type::Any => "any",
+        };
+        f.write_str(name)
+    }
+}
+
+impl Expression {
+    pub fn parse(source: &str) -> Result<Self, DqrError> {
+        let source = source.trim();
+        let invalid = |msg: String| {
+            DqrError::InvalidRuleConfig(format!("invalid expression '{}': {}", source, msg))
+        };
+
+        let tokens = tokenize(source).map_err(invalid)?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let root = parser.parse_or().map_err(invalid)?;
+        if let Some(token) = parser.tokens.get(parser.pos) {
+            return Err(invalid(format!("unexpected {}", token)));
+        }
+
+        // The expression must be able to produce a boolean
+        match root.check().map_err(invalid)? {
+            Type::Bool | Type::Any => {},
+            other => return Err(invalid(format!("must evaluate to a boolean, not a {}", other))),
+        }
+
+        Ok(Expression { source: source.to_string(), root })
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    // Whether the expression holds for the selected value. Runtime errors (a missing
+    // field in arithmetic, division by zero, ...) count as not holding.
+    pub fn holds(&self, current: &Value, ctx: &CheckContext) -> bool {
+        matches!(self.root.eval(current, ctx), Ok(Val::Bool(true)))
+    }
+}
+
+impl Expr {
+    // Infer the static type, rejecting operations that can never succeed
+    fn check(&self) -> Result<Type, String> {
+        match self {
+            Expr::Literal(val) => Ok(val.type_of()),
+            Expr::Path { .. } | Expr::Value => Ok(Type::Any),
+            Expr::Unary(UnaryOp::Neg, operand) => expect(operand.check()?, &[Type::Num], "-").map(|_| Type::Num),
+            Expr::Unary(UnaryOp::Not, operand) => expect(operand.check()?, &[Type::Bool], "!").map(|_| Type::Bool),
+            Expr::Binary(op, left, right) => {
+                let (left, right) = (left.check()?, right.check()?);
+                match op {
+                    BinaryOp::Add if left == Type::Str || right == Type::Str => {
+                        expect(left, &[Type::Str], "+")?;
+                        expect(right, &[Type::Str], "+")?;
+                        Ok(Type::Str)
+                    },
+                    BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Rem => {
+                        let symbol = op.symbol();
+                        expect(left, &[Type::Num], symbol)?;
+                        expect(right, &[Type::Num], symbol)?;
+                        // "+" on two document values may still concatenate strings
+                        Ok(if *op == BinaryOp::Add && left == Type::Any && right == Type::Any { Type::Any } else { Type::Num })
+                    },
+                    BinaryOp::Eq | BinaryOp::Ne => Ok(Type::Bool),
+                    BinaryOp::Lt | BinaryOp::Le | BinaryOp::Gt | BinaryOp::Ge => {
+                        let symbol = op.symbol();
+                        expect(left, &[Type::Num, Type::Str, Type::Date], symbol)?;
+                        expect(right, &[Type::Num, Type::Str, Type::Date], symbol)?;
+                        if left != Type::Any && right != Type::Any && left != right {
+                            return Err(format!("cannot compare {} with {}", left, right));
+                        }
+                        Ok(Type::Bool)
+                    },
+                    BinaryOp::And | BinaryOp::Or => {
+                        let symbol = op.symbol();
+                        expect(left, &[Type::Bool], symbol)?;
+                        expect(right, &[Type::Bool], symbol)?;
+                        Ok(Type::Bool)
+                    },
+                }
+            },
+            Expr::Call(function, args) => {
+                let types = args.iter().map(|arg| arg.check()).collect::<Result<Vec<_>, _>>()?;
+                function.check(&types)
+            },
+        }
+    }
+
+    fn eval(&self, current: &Value, ctx: &CheckContext) -> Result<Val, String> {
+        match self {
+            Expr::Literal(val) => Ok(val.clone()),
+            Expr::Path { path, relative, singular } => {
+                let target = if *relative { ctx.scope } else { ctx.root };
+                let nodes = path.query(target).all();
+                Ok(match (nodes.as_slice(), singular) {
+                    ([], true) => Val::Null,
+                    ([value], true) => Val::from_json(value),
+                    (values, _) => Val::List(values.iter().map(|v| Val::from_json(v)).collect()),
+                })
+            },
+            Expr::Value => Ok(Val::from_json(current)),
+            Expr::Unary(UnaryOp::Neg, operand) => Ok(Val::Num(-operand.eval(current, ctx)?.as_num()?)),
+            Expr::Unary(UnaryOp::Not, operand) => Ok(Val::Bool(!operand.eval(current, ctx)?.as_bool()?)),
+            // Short-circuit so the right-hand side may assume the left held
+            Expr::Binary(BinaryOp::And, left, right) => {
+                Ok(Val::Bool(left.eval(current, ctx)?.as_bool()? && right.eval(current, ctx)?.as_bool()?))
+            },
+            Expr::Binary(BinaryOp::Or, left, right) => {
+                Ok(Val::Bool(left.eval(current, ctx)?.as_bool()? || right.eval(current, ctx)?.as_bool()?))
+            },
+            Expr::Binary(op, left, right) => {
+                let (left, right) = (left.eval(current, ctx)?, right.eval(current, ctx)?);
+                op.apply(left, right)
+            },
+            Expr::Call(function, args) => {
+                let args = args.iter().map(|arg| arg.eval(current, ctx)).collect::<Result<Vec<_>, _>>()?;
+                function.apply(args, ctx)
+            },
+        }
+    }
+}
+
+// Accept a static type if it is one of the allowed types or not yet known
+fn expect(actual: Type, allowed: &[Type], operator: &str) -> Result<Type, String> {
+    if actual == Type::Any || allowed.contains(&actual) {
+        Ok(actual)
+    } else {
+        Err(format!("'{}' cannot be applied to a {}", operator, actual))
+    }
+}
+
+impl BinaryOp {
+    fn symbol(&self) -> &'static str {
+        match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::Rem => "%",
+            BinaryOp::Eq => "==",
+            BinaryOp::Ne => "!=",
+            BinaryOp::Lt => "<",
+            BinaryOp::Le => "<=",
+            BinaryOp::Gt => ">",
+            BinaryOp::Ge => ">=",
+            BinaryOp::And => "&&",
+            BinaryOp::Or => "||",
+        }
+    }
+
+    fn apply(&self, left: Val, right: Val) -> Result<Val, String> {
+        match self {
+            BinaryOp::Add => match (left, right) {
+                (Val::Str(a), Val::Str(b)) => Ok(Val::Str(a + &b)),
+                (left, right) => Ok(Val::Num(left.as_num()? + right.as_num()?)),
+            },
+            BinaryOp::Sub => Ok(Val::Num(left.as_num()? - right.as_num()?)),
+            BinaryOp::Mul => Ok(Val::Num(left.as_num()? * right.as_num()?)),
+            BinaryOp::Div | BinaryOp::Rem => {
+                let (a, b) = (left.as_num()?, right.as_num()?);
+                if b == 0.0 {
+                    return Err("division by zero".to_string());
+                }
+                Ok(Val::Num(if *self == BinaryOp::Div { a / b } else { a % b }))
+            },
+            BinaryOp::Eq => Ok(Val::Bool(left.compare(&right) == Some(Ordering::Equal))),
+            BinaryOp::Ne => Ok(Val::Bool(left.compare(&right) != Some(Ordering::Equal))),
+            BinaryOp::Lt | BinaryOp::Le | BinaryOp::Gt | BinaryOp::Ge => {
+                let ordering = left
+                    .compare(&right)
+                    .ok_or_else(|| format!("cannot compare {} with {}", left.type_of(), right.type_of()))?;
+                Ok(Val::Bool(match self {
+                    BinaryOp::Lt => ordering == Ordering::Less,
+                    BinaryOp::Le => ordering != Ordering::Greater,
+                    BinaryOp::Gt => ordering == Ordering::Greater,
+                    _ => ordering != Ordering::Less,
+                }))
+            },
+            BinaryOp::And | BinaryOp::Or => unreachable!("logical operators short-circuit in Expr::eval"),
+        }
+    }
+}
+
+impl Function {
+    fn lookup(name: &str) -> Option<Self> {
+        Some(match name {
+            "len" => Function::Len,
+            "sum" => Function::Sum,
+            "abs" => Function::Abs,
+            "lower" => Function::Lower,
+            "upper" => Function::Upper,
+            "trim" => Function::Trim,
+            "contains" => Function::Contains,
+            "starts_with" => Function::StartsWith,
+            "ends_with" => Function::EndsWith,
+            "now" => Function::Now,
+            "date" => Function::Date,
+            "days_between" => Function::DaysBetween,
+            _ => return None,
+        })
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Function::Len => "len",
+            Function::Sum => "sum",
+            Function::Abs => "abs",
+            Function::Lower => "lower",
+            Function::Upper => "upper",
+            Function::Trim => "trim",
+            Function::Contains => "contains",
+            Function::StartsWith => "starts_with",
+            Function::EndsWith => "ends_with",
+            Function::Now => "now",
+            Function::Date => "date",
+            Function::DaysBetween => "days_between",
+        }
+    }
+
+    // Parameter types and result type of each function
+    fn signature(&self) -> (&'static [&'static [Type]], Type) {
+        const STR: &[Type] = &[Type::Str];
+        const NUM: &[Type] = &[Type::Num];
+        const DATE: &[Type] = &[Type::Date, Type::Str];
+        match self {
+            Function::Len => (&[&[Type::Str, Type::List]], Type::Num),
+            Function::Sum => (&[&[Type::List, Type::Num]], Type::Num),
+            Function::Abs => (&[NUM], Type::Num),
+            Function::Lower | Function::Upper | Function::Trim => (&[STR], Type::Str),
+            Function::Contains => (&[&[Type::Str, Type::List], &[Type::Str, Type::Num, Type::Bool]], Type::Bool),
+            Function::StartsWith | Function::EndsWith => (&[STR, STR], Type::Bool),
+            Function::Now => (&[], Type::Date),
+            Function::Date => (&[DATE], Type::Date),
+            Function::DaysBetween => (&[DATE, DATE], Type::Num),
+        }
+    }
+
+    fn check(&self, args: &[Type]) -> Result<Type, String> {
+        let (params, result) = self.signature();
+        if args.len() != params.len() {
+            return Err(format!("{}() takes {} argument(s) but {} were given", self.name(), params.len(), args.len()));
+        }
+        for (arg, allowed) in args.iter().zip(params) {
+            if *arg != Type::Any && !allowed.contains(arg) {
+                return Err(format!("{}() cannot be applied to a {}", self.name(), arg));
+            }
+        }
+        Ok(result)
+    }
+
+    fn apply(&self, args: Vec<Val>, ctx: &CheckContext) -> Result<Val, String> {
+        let mut args = args.into_iter();
+        let mut next = || args.next().unwrap_or(Val::Null);
+
+        match self {
+            Function::Len => match next() {
+                Val::Str(s) => Ok(Val::Num(s.chars().count() as f64)),
+                Val::List(items) => Ok(Val::Num(items.len() as f64)),
+                other => Err(format!("len() cannot be applied to a {}", other.type_of())),
+            },
+            Function::Sum => match next() {
+                Val::List(items) => items.iter().map(Val::as_num).sum::<Result<f64, _>>().map(Val::Num),
+                other => other.as_num().map(Val::Num),
+            },
+            Function::Abs => Ok(Val::Num(next().as_num()?.abs())),
+            Function::Lower => Ok(Val::Str(next().as_str()?.to_lowercase())),
+            Function::Upper => Ok(Val::Str(next().as_str()?.to_uppercase())),
+            Function::Trim => Ok(Val::Str(next().as_str()?.trim().to_string())),
+            Function::Contains => match (next(), next()) {
+                (Val::Str(s), needle) => Ok(Val::Bool(s.contains(needle.as_str()?.as_str()))),
+                (Val::List(items), needle) => Ok(Val::Bool(items.contains(&needle))),
+                (other, _) => Err(format!("contains() cannot be applied to a {}", other.type_of())),
+            },
+            Function::StartsWith => {
+                let (s, prefix) = (next().as_str()?, next().as_str()?);
+                Ok(Val::Bool(s.starts_with(&prefix)))
+            },
+            Function::EndsWith => {
+                let (s, suffix) = (next().as_str()?, next().as_str()?);
+                Ok(Val::Bool(s.ends_with(&suffix)))
+            },
+            // Today's date, so results are stable for a day like other relative date checks
+            Function::Now => Ok(Val::Date(DateValue::Date(ctx.now.date_naive()))),
+            Function::Date => next().as_date().map(Val::Date),
+            Function::DaysBetween => {
+                let (from, to) = (next().as_date()?, next().as_date()?);
+                Ok(Val::Num((to.date() - from.date()).num_days() as f64))
+            },
+        }
+    }
+}
+
+impl Val {
+    fn from_json(value: &Value) -> Self {
+        match value {
+            Value::Null => Val::Null,
+            Value::Bool(b) => Val::Bool(*b),
+            Value::Number(n) => n.as_f64().map_or(Val::Null, Val::Num),
+            Value::String(s) => Val::Str(s.clone()),
+            Value::Array(items) => Val::List(items.iter().map(Val::from_json).collect()),
+            // Objects can't take part in expressions; treat them like a missing value
+            Value::Object(_) => Val::Null,
+        }
+    }
+
+    fn type_of(&self) -> Type {
+        match self {
+            Val::Null => Type::Null,
+            Val::Bool(_) => Type::Bool,
+            Val::Num(_) => Type::Num,
+            Val::Str(_) => Type::Str,
+            Val::Date(_) => Type::Date,
+            Val::List(_) => Type::List,
+        }
+    }
+
+    fn as_num(&self) -> Result<f64, String> {
+        match self {
+            Val::Num(n) => Ok(*n),
+            other => Err(format!("expected a number but found a {}", other.type_of())),
+        }
+    }
+
+    fn as_bool(&self) -> Result<bool, String> {
+        match self {
+            Val::Bool(b) => Ok(*b),
+            other => Err(format!("expected a boolean but found a {}", other.type_of())),
+        }
+    }
+
+    fn as_str(&self) -> Result<String, String> {
+        match self {
+            Val::Str(s) => Ok(s.clone()),
+            other => Err(format!("expected a string but found a {}", other.type_of())),
+        }
+    }
+
+    fn as_date(&self) -> Result<DateValue, String> {
+        match self {
+            Val::Date(date) => Ok(*date),
+            Val::Str(s) => DateValue::parse(s).ok_or_else(|| format!("'{}' is not a date", s)),
+            other => Err(format!("expected a date but found a {}", other.type_of())),
+        }
+    }
+
+    // Values of different types are never equal and have no order
+    fn compare(&self, other: &Val) -> Option<Ordering> {
+        match (self, other) {
+            (Val::Num(a), Val::Num(b)) => a.partial_cmp(b),
+            (Val::Str(a), Val::Str(b)) => Some(a.cmp(b)),
+            (Val::Date(a), Val::Date(b)) => Some(a.compare(b)),
+            (a, b) if a == b => Some(Ordering::Equal),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Num(f64),
+    Str(String),
+    Path(String),
+    Ident(String),
+    Op(&'static str),
+    LParen,
+    RParen,
+    Comma,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Num(n) => write!(f, "'{}'", n),
+            Token::Str(s) => write!(f, "'\"{}\"'", s),
+            Token::Path(p) | Token::Ident(p) => write!(f, "'{}'", p),
+            Token::Op(op) => write!(f, "'{}'", op),
+            Token::LParen => f.write_str("'('"),
+            Token::RParen => f.write_str("')'"),
+            Token::Comma => f.write_str("','"),
+        }
+    }
+}
+
+const OPERATORS: [&str; 15] = ["==", "!=", "<=", ">=", "&&", "||", "<", ">", "+", "-", "*", "/", "%", "!", "="];
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            c if c.is_whitespace() => i += 1,
+            '(' => {
+                tokens.push(Token::LParen);
+                i += 1;
+            },
+            ')' => {
+                tokens.push(Token::RParen);
+                i += 1;
+            },
+            ',' => {
+                tokens.push(Token::Comma);
+                i += 1;
+            },
+            '$' | '@' => {
+                // A JSONPath runs over names, dots, wildcards and bracketed segments
+                let start = i;
+                i += 1;
+                let mut depth = 0;
+                let mut quote = None;
+                while i < chars.len() {
+                    let c = chars[i];
+                    match (quote, c) {
+                        (Some(q), c) if c == q => quote = None,
+                        (Some(_), _) => {},
+                        (None, '\'' | '"') if depth > 0 => quote = Some(c),
+                        (None, '[') => depth += 1,
+                        (None, ']') if depth > 0 => depth -= 1,
+                        (None, _) if depth > 0 => {},
+                        (None, '*') if matches!(chars[i - 1], '.') => {},
+                        (None, c) if c.is_alphanumeric() || c == '_' || c == '.' => {},
+                        _ => break,
+                    }
+                    i += 1;
+                }
+                if depth > 0 {
+                    return Err("unclosed '[' in path".to_string());
+                }
+                tokens.push(Token::Path(chars[start..i].iter().collect()));
+            },
+            '"' | '\'' => {
+                let quote = c;
+                let mut s = String::new();
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        None => return Err("unterminated string".to_string()),
+                        Some('\\') => {
+                            s.extend(chars.get(i + 1));
+                            i += 2;
+                        },
+                        Some(&c) if c == quote => {
+                            i += 1;
+                            break;
+                        },
+                        Some(&c) => {
+                            s.push(c);
+                            i += 1;
+                        },
+                    }
+                }
+                tokens.push(Token::Str(s));
+            },
+            c if c.is_ascii_digit() => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                    i += 1;
+                }
+                let text: String = chars[start..i].iter().collect();
+                let n = text.parse().map_err(|_| format!("invalid number '{}'", text))?;
+                tokens.push(Token::Num(n));
+            },
+            c if c.is_alphabetic() || c == '_' => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                tokens.push(Token::Ident(chars[start..i].iter().collect()));
+            },
+            _ => {
+                let rest: String = chars[i..].iter().take(2).collect();
+                let op = OPERATORS
+                    .iter()
+                    .find(|op| rest.starts_with(*op))
+                    .ok_or_else(|| format!("unexpected character '{}'", c))?;
+                if *op == "=" {
+                    return Err("use '==' for comparison".to_string());
+                }
+                tokens.push(Token::Op(op));
+                i += op.len();
+            },
+        }
+    }
+
+    Ok(tokens)
+}
+
+// Precedence, loosest first: ||, &&, comparisons, + -, * / %, unary - !
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    // Consume one of the given operators (keywords "and", "or", "not" are aliases)
+    fn eat_op(&mut self, ops: &[&'static str]) -> Option<&'static str> {
+        let op = match self.peek()? {
+            Token::Op(op) => *op,
+            Token::Ident(word) => match word.as_str() {
+                "and" => "&&",
+                "or" => "||",
+                "not" => "!",
+                _ => return None,
+            },
+            _ => return None,
+        };
+        if ops.contains(&op) {
+            self.pos += 1;
+            Some(op)
+        } else {
+            None
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, String> {
+        let mut left = self.parse_and()?;
+        while self.eat_op(&["||"]).is_some() {
+            left = Expr::Binary(BinaryOp::Or, Box::new(left), Box::new(self.parse_and()?));
+        }
+        Ok(left)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, String> {
+        let mut left = self.parse_comparison()?;
+        while self.eat_op(&["&&"]).is_some() {
+            left = Expr::Binary(BinaryOp::And, Box::new(left), Box::new(self.parse_comparison()?));
+        }
+        Ok(left)
+    }
+
+    fn parse_comparison(&mut self) -> Result<Expr, String> {
+        let left = self.parse_additive()?;
+        let Some(op) = self.eat_op(&["==", "!=", "<", "<=", ">", ">="]) else {
+            return Ok(left);
+        };
+        let op = match op {
+            "==" => BinaryOp::Eq,
+            "!=" => BinaryOp::Ne,
+            "<" => BinaryOp::Lt,
+            "<=" => BinaryOp::Le,
+            ">" => BinaryOp::Gt,
+            _ => BinaryOp::Ge,
+        };
+        Ok(Expr::Binary(op, Box::new(left), Box::new(self.parse_additive()?)))
+    }
+
+    fn parse_additive(&mut self) -> Result<Expr, String> {
+        let mut left = self.parse_multiplicative()?;
+        while let Some(op) = self.eat_op(&["+", "-"]) {
+            let op = if op == "+" { BinaryOp::Add } else { BinaryOp::Sub };
+            left = Expr::Binary(op, Box::new(left), Box::new(self.parse_multiplicative()?));
+        }
+        Ok(left)
+    }
+
+    fn parse_multiplicative(&mut self) -> Result<Expr, String> {
+        let mut left = self.parse_unary()?;
+        while let Some(op) = self.eat_op(&["*", "/", "%"]) {
+            let op = match op {
+                "*" => BinaryOp::Mul,
+                "/" => BinaryOp::Div,
+                _ => BinaryOp::Rem,
+            };
+            left = Expr::Binary(op, Box::new(left), Box::new(self.parse_unary()?));
+        }
+        Ok(left)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, String> {
+        match self.eat_op(&["-", "!"]) {
+            Some("-") => Ok(Expr::Unary(UnaryOp::Neg, Box::new(self.parse_unary()?))),
+            Some(_) => Ok(Expr::Unary(UnaryOp::Not, Box::new(self.parse_unary()?))),
+            None => self.parse_primary(),
+        }
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some(Token::Num(n)) => Ok(Expr::Literal(Val::Num(n))),
+            Some(Token::Str(s)) => Ok(Expr::Literal(Val::Str(s))),
+            Some(Token::Path(text)) => {
+                // "@.amount" is queried against the scope element as if it were "$.amount"
+                let relative = text.starts_with('@');
+                let path = parse_selector(&format!("${}", &text[1..])).map_err(|e| e.to_string())?;
+                Ok(Expr::Path { path, relative, singular: is_singular_path(&text[1..]) })
+            },
+            Some(Token::LParen) => {
+                let expr = self.parse_or()?;
+                match self.next() {
+                    Some(Token::RParen) => Ok(expr),
+                    _ => Err("expected ')'".to_string()),
+                }
+            },
+            Some(Token::Ident(name)) => match name.as_str() {
+                "true" => Ok(Expr::Literal(Val::Bool(true))),
+                "false" => Ok(Expr::Literal(Val::Bool(false))),
+                "null" => Ok(Expr::Literal(Val::Null)),
+                "value" => Ok(Expr::Value),
+                _ => {
+                    let function = Function::lookup(&name).ok_or_else(|| format!("unknown function '{}'", name))?;
+                    if self.next() != Some(Token::LParen) {
+                        return Err(format!("expected '(' after '{}'", name));
+                    }
+                    let mut args = Vec::new();
+                    if self.peek() == Some(&Token::RParen) {
+                        self.pos += 1;
+                    } else {
+                        loop {
+                            args.push(self.parse_or()?);
+                            match self.next() {
+                                Some(Token::Comma) => continue,
+                                Some(Token::RParen) => break,
+                                _ => return Err(format!("expected ',' or ')' in call to '{}'", name)),
+                            }
+                        }
+                    }
+                    Ok(Expr::Call(function, args))
+                },
+            },
+            Some(token) => Err(format!("unexpected {}", token)),
+            None => Err("unexpected end of expression".to_string()),
+        }
+    }
+}
+
+// Whether a path can select at most one value: no wildcards, descendant segments, slices,
+// filters or unions, outside of quoted names
+fn is_singular_path(path: &str) -> bool {
+    let mut quote = None;
+    let mut previous = None;
+    for c in path.chars() {
+        match quote {
+            Some(q) if c == q && previous != Some('\\') => quote = None,
+            Some(_) => {},
+            None => match c {
+                '\'' | '"' => quote = Some(c),
+                '*' | '?' | ':' | ',' => return false,
+                '.' if previous == Some('.') => return false,
+                _ => {},
+            },
+        }
+        previous = Some(c);
+    }
+    true
+}
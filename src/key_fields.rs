@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+
+use serde_json::Value;
+
+// Items (rules) indexed by the key fields that trigger them, so the items a payload triggers
+// are found in one walk over the payload instead of by testing every item.
+//
+// Key paths are dot-separated object keys with array indices dropped. A key field triggers
+// its items when the object that would hold it is present, or when its first segment is a
+// top-level key of the payload: requiring only the parent or the root keeps `required` rules
+// firing for missing fields and whole missing subtrees, and top-level key fields always trigger
+// because their parent is the document itself. The parent's segments must appear in order
+// along a payload path but may skip intermediate containers, so
+// "application.individuals.names.name" is triggered by
+// {"application": {"individuals": {"data": [{"names": [...]}]}}}.
+//
+// The parent paths are stored as a trie of key segments. Walking the payload keeps the set of
+// trie nodes matched so far along the current path; each object key either advances a node to
+// its child or skips the key.
+#[derive(Debug, Clone)]
+pub struct KeyFieldIndex<T> {
+    nodes: Vec<IndexNode<T>>,
+    // Items of nested key fields by their first segment, triggered by a top-level key
+    roots: HashMap<String, Vec<T>>,
+}
+
+#[derive(Debug, Clone)]
+struct IndexNode<T> {
+    children: HashMap<String, usize>,
+    items: Vec<T>,
+}
+
+impl<T> Default for IndexNode<T> {
+    fn default() -> Self {
+        IndexNode { children: HashMap::new(), items: Vec::new() }
+    }
+}
+
+impl<T> Default for KeyFieldIndex<T> {
+    fn default() -> Self {
+        KeyFieldIndex { nodes: vec![IndexNode::default()], roots: HashMap::new() }
+    }
+}
+
+impl<T: Clone> KeyFieldIndex<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    // Index an item under a key field (a key path or a JSONPath selector)
+    pub fn insert(&mut self, key_field: &str, item: T) {
+        let normalized = normalize_key_field(key_field);
+        let mut node = 0;
+        if let Some((parent, _)) = normalized.rsplit_once('.') {
+            if let Some((root, _)) = parent.split_once('.') {
+                self.roots.entry(root.to_string()).or_default().push(item.clone());
+            }
+            for segment in parent.split('.') {
+                node = match self.nodes[node].children.get(segment) {
+                    Some(&child) => child,
+                    None => {
+                        self.nodes.push(IndexNode::default());
+                        let child = self.nodes.len() - 1;
+                        self.nodes[node].children.insert(segment.to_string(), child);
+                        child
+                    },
+                };
+            }
+        }
+        self.nodes[node].items.push(item);
+    }
+
+    // The items triggered by a payload, in the order of their trie nodes and then of their
+    // roots. An item can be returned more than once: once per triggered key field, and again
+    // when both its parent and its root trigger it.
+    pub fn triggered(&self, json: &Value) -> Vec<&T> {
+        let mut reached = vec![false; self.nodes.len()];
+        reached[0] = true;
+        self.walk(json, &[0], &mut reached);
+
+        let top_level_keys = json.as_object().into_iter().flat_map(|map| map.keys());
+        reached
+            .iter()
+            .enumerate()
+            .filter(|(_, reached)| **reached)
+            .flat_map(|(node, _)| self.nodes[node].items.iter())
+            .chain(top_level_keys.filter_map(|key| self.roots.get(key)).flatten())
+            .collect()
+    }
+
+    fn walk(&self, value: &Value, matched: &[usize], reached: &mut [bool]) {
+        // Nothing further down can match once every matched node is a leaf
+        if matched.iter().all(|&node| self.nodes[node].children.is_empty()) {
+            return;
+        }
+
+        match value {
+            Value::Object(map) => {
+                for (key, child) in map {
+                    let mut next = matched.to_vec();
+                    for &node in matched {
+                        if let Some(&advanced) = self.nodes[node].children.get(key) {
+                            reached[advanced] = true;
+                            if !next.contains(&advanced) {
+                                next.push(advanced);
+                            }
+                        }
+                    }
+                    self.walk(child, &next, reached);
+                }
+            },
+            Value::Array(items) => {
+                for item in items {
+                    self.walk(item, matched, reached);
+                }
+            },
+            _ => {},
+        }
+    }
+}
+
+// Accept key fields written as JSONPath selectors (e.g. "$.user.names[*].first",
+// as the rule API generates) by dropping the root and any array indices
+pub fn normalize_key_field(key_field: &str) -> String {
+    let key_field = key_field.trim();
+    let key_field = key_field
+        .strip_prefix("$.")
+        .or_else(|| key_field.strip_prefix('$'))
+        .unwrap_or(key_field);
+
+    let mut normalized = String::with_capacity(key_field.len());
+    let mut depth = 0;
+    for c in key_field.chars() {
+        match c {
+            '[' => depth += 1,
+            ']' => depth -= 1,
+            _ if depth == 0 => normalized.push(c),
+            _ => {},
+        }
+    }
+    normalized
+}
@@ -1,8 +1,20 @@
+pub mod cache;
+pub mod compiled;
+pub mod condition;
+pub mod dates;
+pub mod dependency;
 pub mod error;
+pub mod expr;
+pub mod key_fields;
 pub mod models;
+pub mod reference_lists;
+pub mod rule_file;
 pub mod rules;
+pub mod selector;
 pub mod validation;
 pub mod api;
 
 #[cfg(test)]
-mod validation_test;
\ No newline at end of file
+mod validation_test;
+#[cfg(test)]
+mod rules_test;
\ No newline at end of file
@@ -2,10 +2,12 @@ use std::env;
 use std::fs;
 use std::io;
 use std::process;
+use std::time::Duration;
 
 use dqr::api;
+use dqr::cache::CacheConfig;
 use dqr::models::ValidationRequest;
-use dqr::rules::RuleRepository;
+use dqr::rules::{LoadMode, RuleRepository};
 use dqr::validation::ValidationEngine;
 
 async fn run_validation(validation_engine: ValidationEngine, file_path: &str) -> io::Result<()> {
@@ -40,6 +42,28 @@ async fn run_validation(validation_engine: ValidationEngine, file_path: &str) ->
     Ok(())
 }
 
+fn run_conversion(input_path: &str, output_path: &str, lists_path: Option<&str>) -> io::Result<()> {
+    let mut repository = RuleRepository::new();
+    if let Some(lists_path) = lists_path {
+        repository.set_lists_path(lists_path);
+    }
+    
+    // Load strictly so an invalid rule fails the conversion instead of being dropped
+    if let Err(e) = repository.load_file_with_mode(input_path, LoadMode::Strict) {
+        eprintln!("Error loading rules from {}: {}", input_path, e);
+        process::exit(1);
+    }
+    
+    // The output format is chosen by the output file's extension
+    if let Err(e) = repository.export_rules(output_path) {
+        eprintln!("Error writing rules to {}: {}", output_path, e);
+        process::exit(1);
+    }
+    
+    println!("Converted {} rules from {} to {}", repository.rule_count(), input_path, output_path);
+    Ok(())
+}
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     // Load .env file if it exists
@@ -59,18 +83,51 @@ async fn main() -> std::io::Result<()> {
         .expect("Invalid port number");
     let rules_path = env::var("DQR_RULES_PATH")
         .unwrap_or_else(|_| "rules/default.csv".to_string());
+    let rules_watch_interval = env::var("DQR_RULES_WATCH_INTERVAL_SECS")
+        .ok()
+        .map(|secs| secs.parse::<u64>().expect("Invalid rules watch interval"))
+        .filter(|secs| *secs > 0)
+        .map(Duration::from_secs);
+    let cache_config = CacheConfig {
+        capacity: env::var("DQR_CACHE_CAPACITY")
+            .map(|capacity| capacity.parse::<u64>().expect("Invalid cache capacity"))
+            .unwrap_or(CacheConfig::default().capacity),
+        ttl: env::var("DQR_CACHE_TTL_SECS")
+            .ok()
+            .map(|secs| secs.parse::<u64>().expect("Invalid cache TTL"))
+            .filter(|secs| *secs > 0)
+            .map(Duration::from_secs),
+    };
+    let lists_path = env::var("DQR_LISTS_PATH").ok();
+    let load_mode = env::var("DQR_RULES_LOAD_MODE")
+        .map(|mode| LoadMode::parse(&mode).expect("Invalid rules load mode (expected strict or lenient)"))
+        .unwrap_or_default();
+    
+    // Check if we have a convert command with input and output paths
+    if args.len() >= 4 && args[1] == "convert" {
+        return run_conversion(&args[2], &args[3], lists_path.as_deref());
+    }
     
     // Initialize rule repository and validation engine
     let mut rule_repository = RuleRepository::new();
-    match rule_repository.load_from_csv(&rules_path) {
+    if let Some(lists_path) = &lists_path {
+        rule_repository.set_lists_path(lists_path);
+    }
+    // CSV, JSON and YAML rules files are told apart by their extension
+    match rule_repository.load_file_with_mode(&rules_path, load_mode) {
+        Ok(issues) if !issues.is_empty() => {
+            log::warn!("Loaded rules from {} with {} invalid rule(s) skipped", rules_path, issues.len());
+        },
         Ok(_) => log::info!("Successfully loaded rules from {}", rules_path),
         Err(e) => {
-            log::warn!("Failed to load rules from {}: {}", rules_path, e);
-            log::warn!("No validation rules are loaded. All validations will pass.");
+            // Serving without the rules file would pass every payload, and reloads and
+            // rule edits would have no file to read or save
+            log::error!("Failed to load rules from {}: {}", rules_path, e);
+            process::exit(1);
         }
     }
     
-    let validation_engine = ValidationEngine::new(rule_repository);
+    let validation_engine = ValidationEngine::with_cache_config(rule_repository, cache_config);
     
     // Check if we have a validate command and file path
     if args.len() >= 3 && args[1] == "validate" {
@@ -78,5 +135,5 @@ async fn main() -> std::io::Result<()> {
     }
     
     // If no validate command, start the HTTP server
-    api::start_server(validation_engine, &host, port).await
+    api::start_server(validation_engine, &host, port, rules_watch_interval).await
 }
@@ -7,6 +7,15 @@ pub struct ValidationRequest {
     pub journey: String,
     #[serde(default = "default_system")]
     pub system: String,
+    // Evaluate every rule for the journey/system, not just those whose key fields are present
+    #[serde(default)]
+    pub full_evaluation: bool,
+}
+
+// Per-request switches for the validation engine
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ValidationOptions {
+    pub full_evaluation: bool,
 }
 
 // API Response type for rule management endpoints
@@ -23,23 +32,108 @@ pub struct RuleDisplay {
     pub id: String,
     pub field_path: String,
     pub validation_type: String,
-    pub parameters: Option<String>,
+    pub parameters: std::collections::HashMap<String, serde_json::Value>,
     pub description: Option<String>,
     pub journey: String,
     pub system: String,
+    pub key_fields: String,
+    pub depends_on_selector: String,
+    pub depends_on_condition: String,
+    pub logic_type: ConditionalLogic,
+    pub parent_rule_id: String,
+    pub case_value: String,
+    pub scope: String,
 }
 
-// Request to create a new rule
+// Request to create a new rule. Attributes left out get the same defaults as in a rules file,
+// except key_fields, which defaults to the field path.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct NewRuleRequest {
+    // Client-chosen rule ID; a unique one is generated when left out
+    #[serde(default)]
+    pub id: Option<String>,
     pub field_path: String,
     pub validation_type: String,
-    pub parameters: Option<String>,
+    pub parameters: Option<std::collections::HashMap<String, serde_json::Value>>,
     pub description: Option<String>,
     #[serde(default = "default_journey")]
     pub journey: String,
     #[serde(default = "default_system")]
     pub system: String,
+    #[serde(default)]
+    pub key_fields: Option<String>,
+    #[serde(default)]
+    pub depends_on_selector: String,
+    #[serde(default)]
+    pub depends_on_condition: String,
+    #[serde(default)]
+    pub logic_type: ConditionalLogic,
+    #[serde(default)]
+    pub parent_rule_id: String,
+    #[serde(default)]
+    pub case_value: String,
+    #[serde(default)]
+    pub scope: String,
+    // Branch rules (then/else/else_if/case/default) created under this rule, nested to any
+    // depth. On update, Some replaces the rule's existing branches and None keeps them.
+    #[serde(default)]
+    pub branches: Option<Vec<NewRuleRequest>>,
+}
+
+impl Default for NewRuleRequest {
+    fn default() -> Self {
+        NewRuleRequest {
+            id: None,
+            field_path: String::new(),
+            validation_type: String::new(),
+            parameters: None,
+            description: None,
+            journey: default_journey(),
+            system: default_system(),
+            key_fields: None,
+            depends_on_selector: String::new(),
+            depends_on_condition: String::new(),
+            logic_type: ConditionalLogic::default(),
+            parent_rule_id: String::new(),
+            case_value: String::new(),
+            scope: String::new(),
+            branches: None,
+        }
+    }
+}
+
+// Partial update of a rule; fields left out keep their current values
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct RuleUpdateRequest {
+    pub field_path: Option<String>,
+    pub validation_type: Option<String>,
+    pub parameters: Option<std::collections::HashMap<String, serde_json::Value>>,
+    pub description: Option<String>,
+    pub journey: Option<String>,
+    pub system: Option<String>,
+    pub key_fields: Option<String>,
+    pub depends_on_selector: Option<String>,
+    pub depends_on_condition: Option<String>,
+    pub logic_type: Option<ConditionalLogic>,
+    pub parent_rule_id: Option<String>,
+    pub case_value: Option<String>,
+    pub scope: Option<String>,
+}
+
+// One step of a bulk rule change
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum BulkOperation {
+    Create { rule: NewRuleRequest },
+    Update { id: String, rule: NewRuleRequest },
+    Patch { id: String, changes: RuleUpdateRequest },
+    Delete { id: String },
+}
+
+// Request to apply several rule changes at once; either all of them apply or none do
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BulkRuleRequest {
+    pub operations: Vec<BulkOperation>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -52,9 +146,42 @@ pub struct ValidationResponse {
 pub struct ValidationError {
     pub path: String,
     pub rule_id: String,
+    // JSON Pointer (RFC 6901) of the offending element, when it exists in the payload
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pointer: Option<String>,
+    // The condition that failed, e.g. "min_length:2"
+    #[serde(default)]
+    pub condition: String,
+    // The value observed at the path (absent when the field is missing)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub actual_value: Option<serde_json::Value>,
+    #[serde(default)]
+    pub severity: Severity,
+    #[serde(default)]
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    #[default]
+    Error,
+    Warning,
+    Info,
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+impl Severity {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "error" => Some(Severity::Error),
+            "warning" | "warn" => Some(Severity::Warning),
+            "info" => Some(Severity::Info),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq)]
 pub enum ConditionalLogic {
     #[serde(rename = "if")]
     If,
@@ -62,13 +189,34 @@ pub enum ConditionalLogic {
     Then,
     #[serde(rename = "else")]
     Else,
+    // An "if" rule evaluated in the else branch of its parent, for else-if chains
+    #[serde(rename = "else_if")]
+    ElseIf,
+    // Selects the "case" branch whose case_value equals the selected value, or "default"
+    #[serde(rename = "switch")]
+    Switch,
+    #[serde(rename = "case")]
+    Case,
+    #[serde(rename = "default")]
+    Default,
     #[serde(rename = "standard")]
+    #[default]
     Standard,
 }
 
-impl Default for ConditionalLogic {
-    fn default() -> Self {
-        ConditionalLogic::Standard
+impl ConditionalLogic {
+    // The name used for the logic type in rules files
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConditionalLogic::If => "if",
+            ConditionalLogic::Then => "then",
+            ConditionalLogic::Else => "else",
+            ConditionalLogic::ElseIf => "else_if",
+            ConditionalLogic::Switch => "switch",
+            ConditionalLogic::Case => "case",
+            ConditionalLogic::Default => "default",
+            ConditionalLogic::Standard => "standard",
+        }
     }
 }
 
@@ -86,21 +234,40 @@ pub struct ValidationRule {
     pub depends_on_selector: String,
     #[serde(default = "String::new")]
     pub depends_on_condition: String,
-    #[serde(default = "default_empty_map")]
+    #[serde(default = "default_empty_map", deserialize_with = "deserialize_parameters")]
     pub parameters: std::collections::HashMap<String, serde_json::Value>,
     #[serde(default)]
     pub logic_type: ConditionalLogic,
     #[serde(default = "String::new")]
     pub parent_rule_id: String,
+    // Value a "case" rule matches against its switch rule's selected value
+    #[serde(default = "String::new")]
+    pub case_value: String,
+    // Selector of the elements (e.g. "$.application.individuals.data[*]") the rule and its
+    // branches are evaluated against one at a time, with "@" selectors relative to each
+    #[serde(default = "String::new")]
+    pub scope: String,
 }
 
 impl ValidationRule {
     pub fn is_conditional_branch(&self) -> bool {
-        matches!(self.logic_type, ConditionalLogic::Then | ConditionalLogic::Else)
+        matches!(
+            self.logic_type,
+            ConditionalLogic::Then
+                | ConditionalLogic::Else
+                | ConditionalLogic::ElseIf
+                | ConditionalLogic::Case
+                | ConditionalLogic::Default
+        )
     }
     
+    // "if" and "else_if" rules choose between their then and else branches
     pub fn is_condition_root(&self) -> bool {
-        matches!(self.logic_type, ConditionalLogic::If)
+        matches!(self.logic_type, ConditionalLogic::If | ConditionalLogic::ElseIf)
+    }
+    
+    pub fn is_switch(&self) -> bool {
+        matches!(self.logic_type, ConditionalLogic::Switch)
     }
 }
 
@@ -116,6 +283,48 @@ fn default_empty_map() -> std::collections::HashMap<String, serde_json::Value> {
     std::collections::HashMap::new()
 }
 
+// Parameters are an object in JSON (API requests and responses) and a JSON-encoded
+// column in CSV rules files, where an empty column means no parameters
+fn deserialize_parameters<'de, D>(
+    deserializer: D,
+) -> Result<std::collections::HashMap<String, serde_json::Value>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    use serde::de::{self, MapAccess, Visitor};
+    
+    struct ParametersVisitor;
+    
+    impl<'de> Visitor<'de> for ParametersVisitor {
+        type Value = std::collections::HashMap<String, serde_json::Value>;
+        
+        fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            f.write_str("a map of parameters or a JSON-encoded object")
+        }
+        
+        fn visit_str<E: de::Error>(self, s: &str) -> Result<Self::Value, E> {
+            if s.trim().is_empty() {
+                return Ok(default_empty_map());
+            }
+            serde_json::from_str(s).map_err(|e| E::custom(format!("invalid parameters '{}': {}", s, e)))
+        }
+        
+        fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+            Ok(default_empty_map())
+        }
+        
+        fn visit_none<E: de::Error>(self) -> Result<Self::Value, E> {
+            Ok(default_empty_map())
+        }
+        
+        fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+            Deserialize::deserialize(de::value::MapAccessDeserializer::new(map))
+        }
+    }
+    
+    deserializer.deserialize_any(ParametersVisitor)
+}
+
 impl ValidationResponse {
     pub fn success() -> Self {
         ValidationResponse {
@@ -130,4 +339,12 @@ impl ValidationResponse {
             errors,
         }
     }
+
+    // Build a response from collected errors; only error-severity entries make it invalid
+    pub fn from_errors(errors: Vec<ValidationError>) -> Self {
+        ValidationResponse {
+            valid: !errors.iter().any(|e| e.severity == Severity::Error),
+            errors,
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,98 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use serde_json::Value;
+
+use crate::error::DqrError;
+
+// Named lists of allowed values (country codes, currencies, ...) that `in:@name`
+// conditions refer to instead of inlining the values into every rule
+#[derive(Debug, Clone, Default)]
+pub struct ReferenceLists {
+    lists: HashMap<String, Arc<HashSet<String>>>,
+}
+
+impl ReferenceLists {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Load every .csv and .json file in a directory; each list is named after its file stem
+    pub fn load_dir<P: AsRef<Path>>(dir: P) -> Result<Self, DqrError> {
+        let mut lists = ReferenceLists::new();
+
+        let mut paths: Vec<_> = fs::read_dir(dir.as_ref())?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| matches!(path.extension().and_then(|e| e.to_str()), Some("csv" | "json")))
+            .collect();
+        paths.sort();
+
+        for path in paths {
+            let name = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .ok_or_else(|| DqrError::Generic(format!("invalid reference list file name {}", path.display())))?
+                .to_string();
+            let values = load_file(&path)?;
+            log::info!("Loaded reference list '{}' with {} values from {}", name, values.len(), path.display());
+            lists.insert(&name, values);
+        }
+
+        Ok(lists)
+    }
+
+    pub fn insert<I, S>(&mut self, name: &str, values: I)
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let values = values.into_iter().map(Into::into).collect();
+        self.lists.insert(name.to_string(), Arc::new(values));
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<HashSet<String>>> {
+        self.lists.get(name).cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.lists.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lists.is_empty()
+    }
+}
+
+// CSV lists take the first column of every row after the header; JSON lists are
+// an array of strings, numbers or booleans
+fn load_file(path: &Path) -> Result<Vec<String>, DqrError> {
+    let invalid = |msg: String| {
+        DqrError::InvalidRuleConfig(format!("invalid reference list {}: {}", path.display(), msg))
+    };
+
+    if path.extension().and_then(|e| e.to_str()) == Some("json") {
+        let content = fs::read_to_string(path)?;
+        let items: Vec<Value> = serde_json::from_str(&content).map_err(|e| invalid(e.to_string()))?;
+        items
+            .into_iter()
+            .map(|item| match item {
+                Value::String(s) => Ok(s),
+                Value::Number(n) => Ok(n.to_string()),
+                Value::Bool(b) => Ok(b.to_string()),
+                other => Err(invalid(format!("unsupported value {}", other))),
+            })
+            .collect()
+    } else {
+        let mut reader = csv::Reader::from_path(path)?;
+        let mut values = Vec::new();
+        for record in reader.records() {
+            let record = record?;
+            if let Some(value) = record.get(0).map(str::trim).filter(|v| !v.is_empty()) {
+                values.push(value.to_string());
+            }
+        }
+        Ok(values)
+    }
+}
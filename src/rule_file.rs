@@ -0,0 +1,418 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+
+use crate::error::DqrError;
+use crate::models::{ConditionalLogic, ValidationRule};
+use crate::rules::RuleIssue;
+
+// Rules read from a rules file, each with the row it was read from
+pub type RuleRows = Vec<(usize, ValidationRule)>;
+
+// Formats a rules file can be written in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RuleFileFormat {
+    #[default]
+    Csv,
+    Json,
+    Yaml,
+}
+
+impl RuleFileFormat {
+    // Choose the format from a file's extension: .json, .yaml or .yml, and CSV otherwise
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()).map(|e| e.to_ascii_lowercase()).as_deref() {
+            Some("json") => RuleFileFormat::Json,
+            Some("yaml" | "yml") => RuleFileFormat::Yaml,
+            _ => RuleFileFormat::Csv,
+        }
+    }
+}
+
+// Read the rules in a file. Rows are CSV line numbers (the header is line 1), or a rule's
+// position in a JSON or YAML file counting nested rules. CSV rows that can't be read are
+// returned as issues; a JSON or YAML file that doesn't parse fails as a whole.
+pub fn read_rules(path: &Path, format: RuleFileFormat) -> Result<(RuleRows, Vec<RuleIssue>), DqrError> {
+    let document: RuleDocument = match format {
+        RuleFileFormat::Csv => return read_csv(path),
+        RuleFileFormat::Json => serde_json::from_str(&fs::read_to_string(path)?)?,
+        RuleFileFormat::Yaml => serde_yaml::from_str(&fs::read_to_string(path)?)?,
+    };
+
+    let mut rows = Vec::new();
+    let mut issues = Vec::new();
+    for entry in document.rules {
+        flatten_entry(entry, None, &mut rows, &mut issues);
+    }
+    Ok((rows, issues))
+}
+
+// Write rules to a file in the given format and sync it to disk. JSON and YAML files
+// nest branch rules under their parent rule.
+pub fn write_rules(path: &Path, format: RuleFileFormat, rules: &[ValidationRule]) -> Result<(), DqrError> {
+    let file = match format {
+        RuleFileFormat::Csv => return write_csv(path, rules),
+        RuleFileFormat::Json => {
+            let mut content = serde_json::to_string_pretty(&nest_rules(rules))?;
+            content.push('\n');
+            write_file(path, &content)?
+        },
+        RuleFileFormat::Yaml => write_file(path, &serde_yaml::to_string(&nest_rules(rules))?)?,
+    };
+    file.sync_all()?;
+    Ok(())
+}
+
+fn write_file(path: &Path, content: &str) -> Result<File, DqrError> {
+    let mut file = File::create(path)?;
+    file.write_all(content.as_bytes())?;
+    Ok(file)
+}
+
+fn read_csv(path: &Path) -> Result<(RuleRows, Vec<RuleIssue>), DqrError> {
+    let mut reader = csv::Reader::from_reader(File::open(path)?);
+    let headers = reader.headers()?.clone();
+
+    // Parse every row, remembering its line number for error reporting
+    let mut rows = Vec::new();
+    let mut issues = Vec::new();
+    for result in reader.records() {
+        let parsed = result.and_then(|record| {
+            let row = record.position().map_or(0, |p| p.line() as usize);
+            record.deserialize::<ValidationRule>(Some(&headers)).map(|rule| (row, rule))
+        });
+        match parsed {
+            Ok(row) => rows.push(row),
+            Err(e) => issues.push(RuleIssue {
+                row: e.position().map_or(0, |p| p.line() as usize),
+                rule_id: String::new(),
+                message: e.to_string(),
+            }),
+        }
+    }
+    Ok((rows, issues))
+}
+
+// A rule as written to a CSV rules file: parameters are JSON-encoded, with sorted keys
+// so saving the same rules twice produces the same file
+#[derive(Serialize)]
+struct CsvRuleRecord<'a> {
+    id: &'a str,
+    selector: &'a str,
+    condition: &'a str,
+    key_fields: &'a str,
+    journey: &'a str,
+    system: &'a str,
+    depends_on_selector: &'a str,
+    depends_on_condition: &'a str,
+    parameters: String,
+    logic_type: &'a ConditionalLogic,
+    parent_rule_id: &'a str,
+    case_value: &'a str,
+    scope: &'a str,
+}
+
+impl<'a> CsvRuleRecord<'a> {
+    fn new(rule: &'a ValidationRule) -> Result<Self, DqrError> {
+        let parameters = if rule.parameters.is_empty() {
+            String::new()
+        } else {
+            serde_json::to_string(&rule.parameters.iter().collect::<BTreeMap<_, _>>())?
+        };
+
+        Ok(CsvRuleRecord {
+            id: &rule.id,
+            selector: &rule.selector,
+            condition: &rule.condition,
+            key_fields: &rule.key_fields,
+            journey: &rule.journey,
+            system: &rule.system,
+            depends_on_selector: &rule.depends_on_selector,
+            depends_on_condition: &rule.depends_on_condition,
+            parameters,
+            logic_type: &rule.logic_type,
+            parent_rule_id: &rule.parent_rule_id,
+            case_value: &rule.case_value,
+            scope: &rule.scope,
+        })
+    }
+}
+
+// Write rules to a CSV file and sync it to disk
+fn write_csv(path: &Path, rules: &[ValidationRule]) -> Result<(), DqrError> {
+    let mut writer = csv::Writer::from_writer(File::create(path)?);
+    for rule in rules {
+        writer.serialize(CsvRuleRecord::new(rule)?)?;
+    }
+
+    let file = writer.into_inner().map_err(|e| DqrError::IoError(e.into_error()))?;
+    file.sync_all()?;
+    Ok(())
+}
+
+// A JSON or YAML rules file: `{"rules": [...]}`
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleDocument {
+    #[serde(default)]
+    rules: Vec<RuleEntry>,
+}
+
+// A rule in a JSON or YAML rules file. Branch rules can be nested under their parent in
+// `then`, `else`, `cases` and `default`, which sets their logic type and parent rule ID;
+// they inherit the parent's journey and system unless they set their own.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleEntry {
+    id: String,
+    selector: String,
+    condition: String,
+    // Defaults to the selector
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    key_fields: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    journey: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    system: Option<String>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    depends_on_selector: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    depends_on_condition: String,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    parameters: BTreeMap<String, Value>,
+    // Only needed where the position doesn't imply it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    logic_type: Option<ConditionalLogic>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    parent_rule_id: String,
+    #[serde(default, deserialize_with = "deserialize_case_value", skip_serializing_if = "String::is_empty")]
+    case_value: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    scope: String,
+    #[serde(default, rename = "then", skip_serializing_if = "Vec::is_empty")]
+    then_rules: Vec<RuleEntry>,
+    #[serde(default, rename = "else", skip_serializing_if = "Vec::is_empty")]
+    else_rules: Vec<RuleEntry>,
+    #[serde(default, rename = "cases", skip_serializing_if = "Vec::is_empty")]
+    case_rules: Vec<RuleEntry>,
+    #[serde(default, rename = "default", skip_serializing_if = "Vec::is_empty")]
+    default_rules: Vec<RuleEntry>,
+}
+
+// Case values may be written as numbers or booleans as well as strings
+fn deserialize_case_value<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    match Value::deserialize(deserializer)? {
+        Value::String(s) => Ok(s),
+        Value::Number(n) => Ok(n.to_string()),
+        Value::Bool(b) => Ok(b.to_string()),
+        Value::Null => Ok(String::new()),
+        other => Err(D::Error::custom(format!("case_value must be a string, number or boolean, not {}", other))),
+    }
+}
+
+// Which branch list of its parent a nested rule is in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Branch {
+    Then,
+    Else,
+    Case,
+    Default,
+}
+
+impl Branch {
+    fn key(self) -> &'static str {
+        match self {
+            Branch::Then => "then",
+            Branch::Else => "else",
+            Branch::Case => "cases",
+            Branch::Default => "default",
+        }
+    }
+
+    fn allows(self, logic_type: &ConditionalLogic) -> bool {
+        matches!(
+            (self, logic_type),
+            (Branch::Then, ConditionalLogic::Then)
+                | (Branch::Else, ConditionalLogic::Else | ConditionalLogic::ElseIf)
+                | (Branch::Case, ConditionalLogic::Case)
+                | (Branch::Default, ConditionalLogic::Default)
+        )
+    }
+}
+
+// The logic type a rule has when it doesn't set one: given by the branch it's nested in,
+// or for a top-level rule by the branches nested under it
+fn implied_logic_type(branch: Option<Branch>, has_if_branches: bool, has_switch_branches: bool) -> ConditionalLogic {
+    match branch {
+        Some(Branch::Then) => ConditionalLogic::Then,
+        Some(Branch::Else) if has_if_branches => ConditionalLogic::ElseIf,
+        Some(Branch::Else) => ConditionalLogic::Else,
+        Some(Branch::Case) => ConditionalLogic::Case,
+        Some(Branch::Default) => ConditionalLogic::Default,
+        None if has_if_branches => ConditionalLogic::If,
+        None if has_switch_branches => ConditionalLogic::Switch,
+        None => ConditionalLogic::Standard,
+    }
+}
+
+// Add a rule and the rules nested under it, in document order. A nested rule whose own
+// logic type or parent rule ID contradicts where it is nested is reported and skipped.
+fn flatten_entry(
+    entry: RuleEntry,
+    parent: Option<(Branch, &ValidationRule)>,
+    rows: &mut RuleRows,
+    issues: &mut Vec<RuleIssue>,
+) {
+    let row = rows.len() + issues.len() + 1;
+    let branch = parent.map(|(branch, _)| branch);
+    let has_if_branches = !entry.then_rules.is_empty() || !entry.else_rules.is_empty();
+    let has_switch_branches = !entry.case_rules.is_empty() || !entry.default_rules.is_empty();
+
+    let logic_type = entry
+        .logic_type
+        .unwrap_or_else(|| implied_logic_type(branch, has_if_branches, has_switch_branches));
+    let mut problem = None;
+    let mut parent_rule_id = entry.parent_rule_id;
+    if let Some((branch, parent)) = parent {
+        if !branch.allows(&logic_type) {
+            problem = Some(format!(
+                "rule '{}': a \"{}\" rule can't be nested under \"{}\"",
+                entry.id,
+                logic_type.as_str(),
+                branch.key()
+            ));
+        } else if !parent_rule_id.is_empty() && parent_rule_id != parent.id {
+            problem = Some(format!(
+                "rule '{}': parent_rule_id '{}' doesn't match the rule it is nested under ('{}')",
+                entry.id, parent_rule_id, parent.id
+            ));
+        }
+        parent_rule_id = parent.id.clone();
+    }
+
+    let rule = ValidationRule {
+        key_fields: entry.key_fields.unwrap_or_else(|| entry.selector.clone()),
+        journey: entry
+            .journey
+            .or_else(|| parent.map(|(_, parent)| parent.journey.clone()))
+            .unwrap_or_else(|| "DEFAULT".to_string()),
+        system: entry
+            .system
+            .or_else(|| parent.map(|(_, parent)| parent.system.clone()))
+            .unwrap_or_else(|| "ALL".to_string()),
+        id: entry.id,
+        selector: entry.selector,
+        condition: entry.condition,
+        depends_on_selector: entry.depends_on_selector,
+        depends_on_condition: entry.depends_on_condition,
+        parameters: entry.parameters.into_iter().collect(),
+        logic_type,
+        parent_rule_id,
+        case_value: entry.case_value,
+        scope: entry.scope,
+    };
+
+    let children = [
+        (Branch::Then, entry.then_rules),
+        (Branch::Else, entry.else_rules),
+        (Branch::Case, entry.case_rules),
+        (Branch::Default, entry.default_rules),
+    ];
+    match problem {
+        Some(message) => issues.push(RuleIssue { row, rule_id: rule.id.clone(), message }),
+        None => rows.push((row, rule.clone())),
+    }
+    for (branch, entries) in children {
+        for child in entries {
+            flatten_entry(child, Some((branch, &rule)), rows, issues);
+        }
+    }
+}
+
+// Build a rules document with branch rules nested under their parent rule. Rules whose
+// parent can't be found stay at the top level with an explicit parent_rule_id.
+fn nest_rules(rules: &[ValidationRule]) -> RuleDocument {
+    let ids: HashSet<&str> = rules.iter().map(|rule| rule.id.as_str()).collect();
+    let mut children: HashMap<&str, Vec<&ValidationRule>> = HashMap::new();
+    for rule in rules {
+        if rule.is_conditional_branch() && ids.contains(rule.parent_rule_id.as_str()) {
+            children.entry(rule.parent_rule_id.as_str()).or_default().push(rule);
+        }
+    }
+
+    let mut visited = HashSet::new();
+    let mut document = RuleDocument::default();
+    for rule in rules {
+        let nested = rule.is_conditional_branch() && ids.contains(rule.parent_rule_id.as_str());
+        if !nested {
+            document.rules.push(nest_entry(rule, None, &children, &mut visited));
+        }
+    }
+
+    // Branch rules in a parent cycle are never reached from a top-level rule
+    for rule in rules {
+        if !visited.contains(rule.id.as_str()) {
+            document.rules.push(nest_entry(rule, None, &children, &mut visited));
+        }
+    }
+    document
+}
+
+fn nest_entry<'a>(
+    rule: &'a ValidationRule,
+    parent: Option<&ValidationRule>,
+    children: &HashMap<&str, Vec<&'a ValidationRule>>,
+    visited: &mut HashSet<&'a str>,
+) -> RuleEntry {
+    visited.insert(&rule.id);
+
+    let mut entry = RuleEntry {
+        id: rule.id.clone(),
+        selector: rule.selector.clone(),
+        condition: rule.condition.clone(),
+        key_fields: Some(rule.key_fields.clone()).filter(|key_fields| *key_fields != rule.selector),
+        journey: Some(rule.journey.clone())
+            .filter(|journey| *journey != parent.map_or("DEFAULT", |parent| parent.journey.as_str())),
+        system: Some(rule.system.clone())
+            .filter(|system| *system != parent.map_or("ALL", |parent| parent.system.as_str())),
+        depends_on_selector: rule.depends_on_selector.clone(),
+        depends_on_condition: rule.depends_on_condition.clone(),
+        parameters: rule.parameters.iter().map(|(k, v)| (k.clone(), v.clone())).collect(),
+        logic_type: None,
+        parent_rule_id: if parent.is_none() { rule.parent_rule_id.clone() } else { String::new() },
+        case_value: rule.case_value.clone(),
+        scope: rule.scope.clone(),
+        ..Default::default()
+    };
+
+    for child in children.get(rule.id.as_str()).into_iter().flatten() {
+        if visited.contains(child.id.as_str()) {
+            continue;
+        }
+        let child_entry = nest_entry(child, Some(rule), children, visited);
+        match child.logic_type {
+            ConditionalLogic::Then => entry.then_rules.push(child_entry),
+            ConditionalLogic::Case => entry.case_rules.push(child_entry),
+            ConditionalLogic::Default => entry.default_rules.push(child_entry),
+            _ => entry.else_rules.push(child_entry),
+        }
+    }
+
+    let branch = parent.map(|_| match rule.logic_type {
+        ConditionalLogic::Then => Branch::Then,
+        ConditionalLogic::Case => Branch::Case,
+        ConditionalLogic::Default => Branch::Default,
+        _ => Branch::Else,
+    });
+    let has_if_branches = !entry.then_rules.is_empty() || !entry.else_rules.is_empty();
+    let has_switch_branches = !entry.case_rules.is_empty() || !entry.default_rules.is_empty();
+    if implied_logic_type(branch, has_if_branches, has_switch_branches) != rule.logic_type {
+        entry.logic_type = Some(rule.logic_type.clone());
+    }
+    entry
+}
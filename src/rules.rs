@@ -1,19 +1,107 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use serde_json::Value;
+use uuid::Uuid;
+use crate::compiled::CompiledRule;
+use crate::condition::value_equals;
 use crate::error::DqrError;
-use crate::models::{NewRuleRequest, RuleDisplay, ValidationRule};
+use crate::key_fields::KeyFieldIndex;
+use crate::models::{BulkOperation, ConditionalLogic, NewRuleRequest, RuleDisplay, RuleUpdateRequest, ValidationRule};
+use crate::reference_lists::ReferenceLists;
+use crate::rule_file::{self, RuleFileFormat};
+
+// Conditional branches (then, else) keyed by the ID of their "if" rule
+type ConditionalBranches = (Vec<Arc<CompiledRule>>, Vec<Arc<CompiledRule>>);
+
+// Case and default branches of a "switch" rule
+#[derive(Debug, Clone, Default)]
+pub struct SwitchBranches {
+    pub cases: Vec<Arc<CompiledRule>>,
+    pub default: Vec<Arc<CompiledRule>>,
+}
+
+impl SwitchBranches {
+    // Rules of every case matching the selected value; the default branch when
+    // nothing matches or the switch selector found no value
+    pub fn select(&self, value: Option<&Value>) -> Vec<Arc<CompiledRule>> {
+        let matching: Vec<Arc<CompiledRule>> = match value {
+            Some(value) => self.cases
+                .iter()
+                .filter(|compiled| value_equals(value, &compiled.rule.case_value))
+                .cloned()
+                .collect(),
+            None => Vec::new(),
+        };
+        
+        if matching.is_empty() {
+            self.default.clone()
+        } else {
+            matching
+        }
+    }
+}
+
+// How to treat invalid rows when loading a rules file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LoadMode {
+    // Any invalid row rejects the whole file
+    #[default]
+    Strict,
+    // Invalid rows are logged and skipped
+    Lenient,
+}
+
+impl LoadMode {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "strict" => Some(LoadMode::Strict),
+            "lenient" => Some(LoadMode::Lenient),
+            _ => None,
+        }
+    }
+}
+
+// A problem found while linting a rules file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleIssue {
+    // Line number in a CSV rules file (the header is line 1), or the rule's position in a
+    // JSON or YAML rules file, counting nested rules
+    pub row: usize,
+    pub rule_id: String,
+    pub message: String,
+}
+
+impl std::fmt::Display for RuleIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "row {}: {}", self.row, self.message)
+    }
+}
 
 #[derive(Clone)]
 pub struct RuleRepository {
-    rules: HashMap<String, Vec<ValidationRule>>,
-    pub conditional_rules: HashMap<String, (Vec<ValidationRule>, Vec<ValidationRule>)>,
-    // Cache for rules filtered by journey and system
-    journey_system_cache: HashMap<(String, String), Vec<ValidationRule>>,
+    // Compiled rules indexed by key field
+    rules: HashMap<String, Vec<Arc<CompiledRule>>>,
+    pub conditional_rules: HashMap<String, ConditionalBranches>,
+    // Case/default branches keyed by the ID of their "switch" rule
+    pub switch_rules: HashMap<String, SwitchBranches>,
+    // The same rules as `rules`, indexed for matching against the keys of a payload
+    key_index: KeyFieldIndex<Arc<CompiledRule>>,
     // Path to the rules file for saving changes
     rules_file_path: Option<PathBuf>,
+    // Format of the rules file, used when saving and reloading
+    rules_file_format: RuleFileFormat,
+    // Mode the rules file was loaded with, reused on reload
+    load_mode: LoadMode,
+    // Rules skipped when the rules file was loaded leniently; saving would drop them
+    skipped_rules: Vec<RuleIssue>,
     // All rules in a flat list for easier management
     all_rules: Vec<ValidationRule>,
+    // Directory of reference lists; defaults to "lists" next to the rules file
+    lists_path: Option<PathBuf>,
+    // Named value lists that `in:@name` conditions refer to
+    reference_lists: Arc<ReferenceLists>,
 }
 
 impl Default for RuleRepository {
@@ -27,44 +115,185 @@ impl RuleRepository {
         RuleRepository {
             rules: HashMap::new(),
             conditional_rules: HashMap::new(),
-            journey_system_cache: HashMap::new(),
+            switch_rules: HashMap::new(),
+            key_index: KeyFieldIndex::new(),
             rules_file_path: None,
+            rules_file_format: RuleFileFormat::default(),
+            load_mode: LoadMode::default(),
+            skipped_rules: Vec::new(),
             all_rules: Vec::new(),
+            lists_path: None,
+            reference_lists: Arc::new(ReferenceLists::new()),
         }
     }
     
-    // Clear caches when rules change
-    pub fn clear_caches(&mut self) {
-        self.journey_system_cache.clear();
-    }
-    
-    // Set the path to the rules file
+    // Set the path to the rules file; its format is chosen by the file's extension
     pub fn set_rules_file_path<P: AsRef<Path>>(&mut self, path: P) {
         self.rules_file_path = Some(path.as_ref().to_path_buf());
+        self.rules_file_format = RuleFileFormat::from_path(path.as_ref());
+    }
+    
+    // Get the path of the rules file, if rules were loaded from one
+    pub fn rules_file_path(&self) -> Option<&Path> {
+        self.rules_file_path.as_deref()
+    }
+    
+    // Get the format of the rules file
+    pub fn rules_file_format(&self) -> RuleFileFormat {
+        self.rules_file_format
+    }
+    
+    // Get the mode the rules file was loaded with
+    pub fn load_mode(&self) -> LoadMode {
+        self.load_mode
+    }
+    
+    // Get the rules skipped when the rules file was loaded
+    pub fn skipped_rules(&self) -> &[RuleIssue] {
+        &self.skipped_rules
+    }
+    
+    // Set the directory reference lists are loaded from
+    pub fn set_lists_path<P: AsRef<Path>>(&mut self, path: P) {
+        self.lists_path = Some(path.as_ref().to_path_buf());
+    }
+    
+    // Get the configured reference list directory
+    pub fn lists_path(&self) -> Option<&Path> {
+        self.lists_path.as_deref()
+    }
+    
+    // Replace the reference lists used when compiling rules added from now on
+    pub fn set_reference_lists(&mut self, lists: ReferenceLists) {
+        self.reference_lists = Arc::new(lists);
+    }
+    
+    // Get the loaded reference lists
+    pub fn reference_lists(&self) -> &ReferenceLists {
+        &self.reference_lists
+    }
+    
+    // Get the number of rules (including conditional branches)
+    pub fn rule_count(&self) -> usize {
+        self.all_rules.len()
+    }
+    
+    // Get every rule, including conditional branches, in file order
+    pub fn all_rules(&self) -> &[ValidationRule] {
+        &self.all_rules
     }
 
+    // Load rules from CSV in strict mode: any invalid row rejects the whole file
     pub fn load_from_csv<P: AsRef<Path>>(
         &mut self,
         path: P,
     ) -> Result<(), DqrError> {
-        // Clear caches and existing rules
-        self.clear_caches();
+        self.load_from_csv_with_mode(path, LoadMode::Strict).map(|_| ())
+    }
+    
+    // Load rules from CSV, linting every row first. In lenient mode invalid rows are
+    // skipped and returned as issues; in strict mode they fail the load.
+    pub fn load_from_csv_with_mode<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        mode: LoadMode,
+    ) -> Result<Vec<RuleIssue>, DqrError> {
+        self.load_with_format(path, RuleFileFormat::Csv, mode)
+    }
+    
+    // Load rules from a JSON rules file in strict mode
+    pub fn load_from_json<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+    ) -> Result<(), DqrError> {
+        self.load_with_format(path, RuleFileFormat::Json, LoadMode::Strict).map(|_| ())
+    }
+    
+    // Load rules from a YAML rules file in strict mode
+    pub fn load_from_yaml<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+    ) -> Result<(), DqrError> {
+        self.load_with_format(path, RuleFileFormat::Yaml, LoadMode::Strict).map(|_| ())
+    }
+    
+    // Load a CSV, JSON or YAML rules file, chosen by the file's extension
+    pub fn load_file_with_mode<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        mode: LoadMode,
+    ) -> Result<Vec<RuleIssue>, DqrError> {
+        let format = RuleFileFormat::from_path(path.as_ref());
+        self.load_with_format(path, format, mode)
+    }
+    
+    // Load rules from a file in the given format, linting every rule first. In lenient
+    // mode invalid rules are skipped and returned as issues; in strict mode they fail the load.
+    pub fn load_with_format<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        format: RuleFileFormat,
+        mode: LoadMode,
+    ) -> Result<Vec<RuleIssue>, DqrError> {
+        let (rows, mut issues) = rule_file::read_rules(path.as_ref(), format)?;
+        
+        // Load the reference lists the rules may refer to
+        let lists_dir = self.lists_path.clone().or_else(|| {
+            path.as_ref().parent().map(|dir| dir.join("lists"))
+        });
+        let reference_lists = match lists_dir {
+            Some(dir) if dir.is_dir() => ReferenceLists::load_dir(&dir)?,
+            _ => ReferenceLists::new(),
+        };
+        
+        // Compile and lint every rule before touching the current state
+        let (compiled_rules, lint_issues) = compile_rules(rows, &reference_lists);
+        issues.extend(lint_issues);
+        
+        if !issues.is_empty() {
+            match mode {
+                LoadMode::Strict => {
+                    return Err(DqrError::InvalidRuleConfig(format!(
+                        "{} problem(s) in {}: {}",
+                        issues.len(),
+                        path.as_ref().display(),
+                        issues.iter().map(|i| i.to_string()).collect::<Vec<_>>().join("; ")
+                    )));
+                },
+                LoadMode::Lenient => {
+                    for issue in &issues {
+                        log::warn!("Skipping invalid rule in {}: {}", path.as_ref().display(), issue);
+                    }
+                }
+            }
+        }
+        
+        // Store the path, format and mode for later use
+        self.rules_file_path = Some(path.as_ref().to_path_buf());
+        self.rules_file_format = format;
+        self.load_mode = mode;
+        self.skipped_rules = issues.clone();
+        self.reference_lists = Arc::new(reference_lists);
+        self.index_rules(compiled_rules);
+        
+        Ok(issues)
+    }
+    
+    // Replace the current rules with an already compiled set
+    fn index_rules(&mut self, compiled_rules: Vec<Arc<CompiledRule>>) {
+        // Clear existing rules
         self.rules.clear();
+        self.key_index.clear();
         self.conditional_rules.clear();
+        self.switch_rules.clear();
         self.all_rules.clear();
         
-        // Store the path for later use
-        self.set_rules_file_path(path.as_ref());
-        
-        let file = File::open(path.as_ref())?;
-        let mut reader = csv::Reader::from_reader(file);
-        
         // Temporary storage for conditional rules
-        let mut then_rules: HashMap<String, Vec<ValidationRule>> = HashMap::new();
-        let mut else_rules: HashMap<String, Vec<ValidationRule>> = HashMap::new();
+        let mut then_rules: HashMap<String, Vec<Arc<CompiledRule>>> = HashMap::new();
+        let mut else_rules: HashMap<String, Vec<Arc<CompiledRule>>> = HashMap::new();
 
-        for result in reader.deserialize() {
-            let rule: ValidationRule = result?;
+        for compiled in compiled_rules {
+            let rule = &compiled.rule;
             
             // Keep a copy of all rules for management operations
             self.all_rules.push(rule.clone());
@@ -76,38 +305,45 @@ impl RuleRepository {
                     then_rules
                         .entry(rule.parent_rule_id.clone())
                         .or_default()
-                        .push(rule);
+                        .push(compiled.clone());
                     continue;
                 },
-                crate::models::ConditionalLogic::Else => {
-                    // Store "else" rules indexed by their parent rule ID
+                crate::models::ConditionalLogic::Else | crate::models::ConditionalLogic::ElseIf => {
+                    // Store "else" and "else_if" rules indexed by their parent rule ID
                     else_rules
                         .entry(rule.parent_rule_id.clone())
                         .or_default()
-                        .push(rule);
+                        .push(compiled.clone());
+                    continue;
+                },
+                crate::models::ConditionalLogic::Case => {
+                    self.switch_rules
+                        .entry(rule.parent_rule_id.clone())
+                        .or_default()
+                        .cases
+                        .push(compiled.clone());
+                    continue;
+                },
+                crate::models::ConditionalLogic::Default => {
+                    self.switch_rules
+                        .entry(rule.parent_rule_id.clone())
+                        .or_default()
+                        .default
+                        .push(compiled.clone());
                     continue;
                 },
                 _ => {
-                    // Standard rules and "if" rules are processed normally
+                    // Standard, "if" and "switch" rules are processed normally
                 }
             }
             
-            // Split comma-separated key fields
-            let key_fields: Vec<String> = if rule.key_fields.is_empty() {
-                Vec::new()
-            } else {
-                rule.key_fields
-                    .split(',')
-                    .map(|s| s.trim().to_string())
-                    .collect()
-            };
-            
             // Insert rule into our map, grouped by each key field
-            for field in &key_fields {
+            for field in &compiled.key_fields {
+                self.key_index.insert(field, compiled.clone());
                 self.rules
                     .entry(field.clone())
                     .or_default()
-                    .push(rule.clone());
+                    .push(compiled.clone());
             }
         }
         
@@ -121,32 +357,65 @@ impl RuleRepository {
         for (parent_id, else_branch) in else_rules {
             self.conditional_rules.insert(parent_id, (Vec::new(), else_branch));
         }
-
-        Ok(())
     }
     
-    // Method to add a rule directly (useful for testing)
-    pub fn add_rule(&mut self, key_field: String, rule: ValidationRule) {
-        self.rules
-            .entry(key_field)
-            .or_default()
-            .push(rule.clone());
+    // Method to add a rule directly (useful for testing).
+    // Branch rules are attached to their parent instead of the key field index.
+    pub fn add_rule(&mut self, key_field: String, rule: ValidationRule) -> Result<(), DqrError> {
+        let compiled = Arc::new(CompiledRule::compile_with_lists(rule.clone(), &self.reference_lists)?);
+        
+        match rule.logic_type {
+            crate::models::ConditionalLogic::Then => {
+                self.conditional_rules
+                    .entry(rule.parent_rule_id.clone())
+                    .or_default()
+                    .0
+                    .push(compiled);
+            },
+            crate::models::ConditionalLogic::Else | crate::models::ConditionalLogic::ElseIf => {
+                self.conditional_rules
+                    .entry(rule.parent_rule_id.clone())
+                    .or_default()
+                    .1
+                    .push(compiled);
+            },
+            crate::models::ConditionalLogic::Case => {
+                self.switch_rules
+                    .entry(rule.parent_rule_id.clone())
+                    .or_default()
+                    .cases
+                    .push(compiled);
+            },
+            crate::models::ConditionalLogic::Default => {
+                self.switch_rules
+                    .entry(rule.parent_rule_id.clone())
+                    .or_default()
+                    .default
+                    .push(compiled);
+            },
+            _ => {
+                self.key_index.insert(&key_field, compiled.clone());
+                self.rules
+                    .entry(key_field)
+                    .or_default()
+                    .push(compiled);
+            }
+        }
         
         // Add to all_rules as well
         self.all_rules.push(rule);
         
-        // Clear caches after rule changes
-        self.clear_caches();
+        Ok(())
     }
 
-    pub fn get_rules_for_key_field(&self, key_field: &str) -> Vec<ValidationRule> {
+    pub fn get_rules_for_key_field(&self, key_field: &str) -> Vec<Arc<CompiledRule>> {
         self.rules
             .get(key_field)
             .cloned()
             .unwrap_or_else(Vec::new)
     }
     
-    pub fn get_rules_for_key_fields(&self, fields: &[String]) -> Vec<ValidationRule> {
+    pub fn get_rules_for_key_fields(&self, fields: &[String]) -> Vec<Arc<CompiledRule>> {
         let mut matched_rules = Vec::new();
         
         // Special case: if fields contains "*", return all rules
@@ -158,8 +427,8 @@ impl RuleRepository {
             }
             
             // Remove duplicates
-            all_rules.sort_by(|a, b| a.id.cmp(&b.id));
-            all_rules.dedup_by(|a, b| a.id == b.id);
+            all_rules.sort_by(|a, b| a.rule.id.cmp(&b.rule.id));
+            all_rules.dedup_by(|a, b| a.rule.id == b.rule.id);
             
             return all_rules;
         }
@@ -172,82 +441,59 @@ impl RuleRepository {
         }
         
         // Remove duplicates (if a rule matches multiple key fields)
-        matched_rules.sort_by(|a, b| a.id.cmp(&b.id));
-        matched_rules.dedup_by(|a, b| a.id == b.id);
+        matched_rules.sort_by(|a, b| a.rule.id.cmp(&b.rule.id));
+        matched_rules.dedup_by(|a, b| a.rule.id == b.rule.id);
         
         matched_rules
     }
     
-    // Get rules filtered by journey and system (with caching)
-    pub fn get_rules_for_journey_system(&self, journey: &str, system: &str) -> Vec<ValidationRule> {
-        // Check if we have a cached result
-        let cache_key = (journey.to_string(), system.to_string());
-        if let Some(cached_rules) = self.journey_system_cache.get(&cache_key) {
-            return cached_rules.clone();
-        }
+    // Get the rules triggered by the key fields present in a payload
+    pub fn get_rules_for_payload(&self, json: &Value) -> Vec<Arc<CompiledRule>> {
+        let mut matched_rules: Vec<Arc<CompiledRule>> = self.key_index
+            .triggered(json)
+            .into_iter()
+            .cloned()
+            .collect();
         
+        // Remove duplicates (if a rule matches multiple key fields)
+        matched_rules.sort_by(|a, b| a.rule.id.cmp(&b.rule.id));
+        matched_rules.dedup_by(|a, b| a.rule.id == b.rule.id);
+        
+        matched_rules
+    }
+    
+    // Get rules filtered by journey and system (cached by the validation engine)
+    pub fn get_rules_for_journey_system(&self, journey: &str, system: &str) -> Vec<Arc<CompiledRule>> {
         // Get all rules
         let all_fields: Vec<String> = vec!["*".to_string()]; // Wildcard to get all rules
         let mut all_rules = self.get_rules_for_key_fields(&all_fields);
         
         // Filter rules based on journey and system
-        all_rules.retain(|rule| {
-            // Match by journey - if journey is ALL_CHECKS, include all rules
-            let journey_match = journey == "ALL_CHECKS" || 
-                                rule.journey == journey || 
-                                (journey == "DEFAULT" && rule.journey == "DEFAULT");
-            
-            // Match by system - if rule's system is ALL, it applies to all systems
-            let system_match = rule.system == "ALL" || rule.system == system;
-            
-            journey_match && system_match
-        });
-        
-        // Note: We can't modify the cache here in a &self method
-        // Cache will be updated separately with an explicit update method
+        all_rules.retain(|compiled| applies_to_journey_system(&compiled.rule, journey, system));
         
         all_rules
     }
     
-    // Update the journey/system cache with a new entry
-    pub fn update_journey_system_cache(&mut self, journey: &str, system: &str, rules: Vec<ValidationRule>) {
-        let cache_key = (journey.to_string(), system.to_string());
-        self.journey_system_cache.insert(cache_key, rules);
-    }
-    
-    // Get journey/system cache size
-    pub fn get_journey_system_cache_size(&self) -> usize {
-        self.journey_system_cache.len()
-    }
-    
     // Get the conditional branches (then/else) for a specific rule ID
-    pub fn get_conditional_rules(&self, parent_id: &str) -> (Vec<ValidationRule>, Vec<ValidationRule>) {
+    pub fn get_conditional_rules(&self, parent_id: &str) -> ConditionalBranches {
         match self.conditional_rules.get(parent_id) {
             Some((then_rules, else_rules)) => (then_rules.clone(), else_rules.clone()),
             None => (Vec::new(), Vec::new())
         }
     }
     
+    // Get the case/default branches for a specific "switch" rule ID
+    pub fn get_switch_rules(&self, parent_id: &str) -> SwitchBranches {
+        self.switch_rules.get(parent_id).cloned().unwrap_or_default()
+    }
+    
     // Rule Management API Methods
     
-    // Get all rules for UI display
+    // Get all rules for UI display, including conditional branches
     pub fn get_all_rules_for_display(&self) -> Vec<RuleDisplay> {
         self.all_rules
             .iter()
-            .filter(|rule| matches!(rule.logic_type, crate::models::ConditionalLogic::Standard))
             .map(|rule| {
-                let parameters = if rule.parameters.is_empty() {
-                    None
-                } else {
-                    // Convert parameters to string format
-                    let param_str = rule.parameters
-                        .iter()
-                        .map(|(k, v)| format!("{}:{}", k, v))
-                        .collect::<Vec<_>>()
-                        .join(",");
-                    Some(param_str)
-                };
-                
                 // The description might be in the parameters map
                 let description = rule.parameters.get("description").and_then(|v| {
                     v.as_str().map(|s| s.to_string())
@@ -257,129 +503,504 @@ impl RuleRepository {
                     id: rule.id.clone(),
                     field_path: rule.selector.clone(),
                     validation_type: rule.condition.clone(),
-                    parameters,
+                    parameters: rule.parameters.clone(),
                     description,
                     journey: rule.journey.clone(),
                     system: rule.system.clone(),
+                    key_fields: rule.key_fields.clone(),
+                    depends_on_selector: rule.depends_on_selector.clone(),
+                    depends_on_condition: rule.depends_on_condition.clone(),
+                    logic_type: rule.logic_type.clone(),
+                    parent_rule_id: rule.parent_rule_id.clone(),
+                    case_value: rule.case_value.clone(),
+                    scope: rule.scope.clone(),
                 }
             })
             .collect()
     }
     
-    // Create a new rule from a request
+    // Get a single rule by ID
+    pub fn get_rule(&self, rule_id: &str) -> Option<&ValidationRule> {
+        self.all_rules.iter().find(|rule| rule.id == rule_id)
+    }
+    
+    // Create a new rule, and any branch rules nested in the request, from a request
     pub fn create_rule(&mut self, request: &NewRuleRequest) -> Result<String, DqrError> {
-        // Generate a unique ID for the new rule
-        let rule_id = format!("R{}", self.all_rules.len() + 1);
-        
-        // Parse parameters from string format to HashMap
-        let mut parameters = HashMap::new();
-        if let Some(param_str) = &request.parameters {
-            for part in param_str.split(',') {
-                if let Some((key, value)) = part.split_once(':') {
-                    // Try to parse the value as JSON, fall back to string if it fails
-                    let json_value = serde_json::from_str(value)
-                        .unwrap_or_else(|_| serde_json::Value::String(value.to_string()));
-                    parameters.insert(key.trim().to_string(), json_value);
-                }
-            }
-        }
+        let mut rules = self.all_rules.clone();
+        let rule_id = push_rule_tree(&mut rules, request, None, &self.skipped_rule_ids())?;
         
-        // Add description to parameters if provided
-        if let Some(desc) = &request.description {
-            parameters.insert("description".to_string(), serde_json::Value::String(desc.clone()));
-        }
+        // Rebuild the indexes (fails if the rule doesn't compile)
+        self.rebuild(rules)?;
         
-        // Create the new rule
-        let new_rule = ValidationRule {
-            id: rule_id.clone(),
-            selector: request.field_path.clone(),
-            condition: request.validation_type.clone(),
-            key_fields: request.field_path.clone(), // Use the field path as the key field
-            journey: request.journey.clone(),
-            system: request.system.clone(),
-            depends_on_selector: String::new(),
-            depends_on_condition: String::new(),
-            parameters,
-            logic_type: crate::models::ConditionalLogic::Standard,
-            parent_rule_id: String::new(),
-        };
+        // Saving to the rules file is done by ValidationEngine::edit_rules, which refuses
+        // the edit with RulesFileIncomplete while a lenient load has skipped rules
         
-        // Add to the repository
-        self.add_rule(request.field_path.clone(), new_rule.clone());
+        Ok(rule_id)
+    }
+    
+    // Replace a rule with the one described by the request, keeping its ID.
+    // Branches in the request replace the rule's existing branches.
+    pub fn update_rule(&mut self, rule_id: &str, request: &NewRuleRequest) -> Result<(), DqrError> {
+        let mut rules = self.all_rules.clone();
+        replace_rule_tree(&mut rules, rule_id, request, &self.skipped_rule_ids())?;
         
-        // Note: We no longer try to save to file here
-        // That's done at the API level with error handling
+        self.rebuild(rules)
+    }
+    
+    // Change only the fields set in the request
+    pub fn patch_rule(&mut self, rule_id: &str, changes: &RuleUpdateRequest) -> Result<(), DqrError> {
+        let mut rules = self.all_rules.clone();
+        let position = rule_position(&rules, rule_id)?;
+        apply_changes(&mut rules[position], changes);
         
-        Ok(rule_id)
+        self.rebuild(rules)
     }
     
-    // Delete a rule by ID
+    // Delete a rule by ID, along with the branch rules nested under it
     pub fn delete_rule(&mut self, rule_id: &str) -> Result<(), DqrError> {
-        let mut found = false;
-        
-        // Remove from all_rules
-        self.all_rules.retain(|rule| {
-            if rule.id == rule_id {
-                found = true;
-                false
-            } else {
-                true
-            }
-        });
+        let mut rules = self.all_rules.clone();
+        remove_rule_tree(&mut rules, rule_id)?;
         
-        if !found {
-            return Err(DqrError::RuleNotFound(rule_id.to_string()));
-        }
+        // Saving to the rules file is done by ValidationEngine::edit_rules, which refuses
+        // the edit with RulesFileIncomplete while a lenient load has skipped rules
         
-        // Remove from rules map
-        for rules_vec in self.rules.values_mut() {
-            rules_vec.retain(|rule| rule.id != rule_id);
-        }
+        self.rebuild(rules)
+    }
+    
+    // Apply several operations in order as one change. If any operation fails, or the
+    // resulting rule set doesn't compile, no rule is changed. Returns the created rule IDs.
+    pub fn apply_bulk(&mut self, operations: &[BulkOperation]) -> Result<Vec<String>, DqrError> {
+        let mut rules = self.all_rules.clone();
+        let skipped_ids = self.skipped_rule_ids();
+        let mut created = Vec::new();
         
-        // Clean up empty entries in the rules map
-        self.rules.retain(|_, rules| !rules.is_empty());
+        for (index, operation) in operations.iter().enumerate() {
+            let with_index = |e: DqrError| match e {
+                DqrError::InvalidRuleConfig(msg) => {
+                    DqrError::InvalidRuleConfig(format!("operation {}: {}", index + 1, msg))
+                },
+                other => other,
+            };
+            
+            match operation {
+                BulkOperation::Create { rule } => {
+                    created.push(push_rule_tree(&mut rules, rule, None, &skipped_ids).map_err(with_index)?);
+                },
+                BulkOperation::Update { id, rule } => {
+                    replace_rule_tree(&mut rules, id, rule, &skipped_ids).map_err(with_index)?;
+                },
+                BulkOperation::Patch { id, changes } => {
+                    let position = rule_position(&rules, id).map_err(with_index)?;
+                    apply_changes(&mut rules[position], changes);
+                },
+                BulkOperation::Delete { id } => {
+                    remove_rule_tree(&mut rules, id).map_err(with_index)?;
+                },
+            }
+        }
         
-        // Remove conditional rule branches if they exist
-        self.conditional_rules.remove(rule_id);
+        self.rebuild(rules)?;
         
-        // Clear caches
-        self.clear_caches();
+        Ok(created)
+    }
+    
+    // IDs of the rules skipped when the rules file was loaded; they stay taken so an edit
+    // can't reuse one of them
+    fn skipped_rule_ids(&self) -> HashSet<String> {
+        self.skipped_rules
+            .iter()
+            .filter(|issue| !issue.rule_id.is_empty())
+            .map(|issue| issue.rule_id.clone())
+            .collect()
+    }
+    
+    // Recompile and re-index a complete rule set. Every management edit goes through
+    // here so the key field index, the branch indexes and all_rules can't drift apart;
+    // if any rule is invalid the current rules are left untouched.
+    fn rebuild(&mut self, rules: Vec<ValidationRule>) -> Result<(), DqrError> {
+        let rows = rules.into_iter().enumerate().map(|(index, rule)| (index + 1, rule)).collect();
+        let (compiled_rules, issues) = compile_rules(rows, &self.reference_lists);
         
-        // Note: We no longer try to save to file here
-        // That's done at the API level with error handling
+        if !issues.is_empty() {
+            let messages: Vec<String> = issues.into_iter().map(|issue| issue.message).collect();
+            return Err(DqrError::InvalidRuleConfig(messages.join("; ")));
+        }
         
+        self.index_rules(compiled_rules);
         Ok(())
     }
     
-    // Save rules to CSV file
+    // Save rules to the rules file, in its format. Every field is written, to a temporary
+    // file that is synced to disk and then renamed over the rules file, so a failed save
+    // never leaves a truncated rules file behind. Refused while rules skipped by a lenient
+    // load are missing from the rule set, as the save would delete them from the file.
     pub fn save_rules_to_file(&self) -> Result<(), DqrError> {
-        if let Some(path) = &self.rules_file_path {
-            // Create a temporary file to write to
-            let temp_path = path.with_extension("csv.tmp");
-            let file = File::create(&temp_path)?;
-            let mut writer = csv::Writer::from_writer(file);
-            
-            // Write all rules
-            for rule in &self.all_rules {
-                // Note: This will still fail with HashMap<String, serde_json::Value>
-                // But we're catching the error and not letting it affect the API response
-                if let Err(_) = writer.serialize(rule) {
-                    // Log that saving is not supported but continue
-                    log::warn!("CSV serialization of rules with parameters is not fully supported. Rules are maintained in memory only.");
-                    // Break out of the loop since we know serialization will fail
-                    break;
-                }
+        let Some(path) = &self.rules_file_path else {
+            return Err(DqrError::Generic("No rules file path set".to_string()));
+        };
+        if !self.skipped_rules.is_empty() {
+            return Err(DqrError::RulesFileIncomplete(format!(
+                "{} invalid rule(s) in {} were skipped when it was loaded and would be lost; fix them and reload the rules first",
+                self.skipped_rules.len(),
+                path.display()
+            )));
+        }
+        write_rules_atomically(path, self.rules_file_format, &self.all_rules)
+    }
+    
+    // Write the rules to another file, in the format chosen by its extension; used to
+    // convert rules files between formats
+    pub fn export_rules<P: AsRef<Path>>(&self, path: P) -> Result<(), DqrError> {
+        let path = path.as_ref();
+        write_rules_atomically(path, RuleFileFormat::from_path(path), &self.all_rules)
+    }
+}
+
+// Write rules to "<path>.tmp", then rename it over the path
+fn write_rules_atomically(path: &Path, format: RuleFileFormat, rules: &[ValidationRule]) -> Result<(), DqrError> {
+    let temp_path = match path.extension().and_then(|e| e.to_str()) {
+        Some(extension) => path.with_extension(format!("{}.tmp", extension)),
+        None => path.with_extension("tmp"),
+    };
+    let result = rule_file::write_rules(&temp_path, format, rules).and_then(|_| {
+        std::fs::rename(&temp_path, path)?;
+        sync_parent_dir(path)
+    });
+    
+    if result.is_err() {
+        // Don't leave a partial temp file around; it may not exist
+        let _ = std::fs::remove_file(&temp_path);
+    }
+    result
+}
+
+// Sync the directory holding a file so a rename into it survives a crash.
+// Directories can only be opened for syncing on Unix.
+fn sync_parent_dir(path: &Path) -> Result<(), DqrError> {
+    #[cfg(unix)]
+    {
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        File::open(dir)?.sync_all()?;
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+// Whether a rule runs for a journey and system
+pub fn applies_to_journey_system(rule: &ValidationRule, journey: &str, system: &str) -> bool {
+    // Match by journey - if journey is ALL_CHECKS, include all rules
+    let journey_match = journey == "ALL_CHECKS" || 
+                        rule.journey == journey || 
+                        (journey == "DEFAULT" && rule.journey == "DEFAULT");
+    
+    // Match by system - if rule's system is ALL, it applies to all systems
+    let system_match = rule.system == "ALL" || rule.system == system;
+    
+    journey_match && system_match
+}
+
+// Build a rule from an API request
+fn rule_from_request(rule_id: &str, request: &NewRuleRequest) -> ValidationRule {
+    let mut parameters = request.parameters.clone().unwrap_or_default();
+    
+    // Add description to parameters if provided
+    if let Some(desc) = &request.description {
+        parameters.insert("description".to_string(), serde_json::Value::String(desc.clone()));
+    }
+    
+    ValidationRule {
+        id: rule_id.to_string(),
+        selector: request.field_path.clone(),
+        condition: request.validation_type.clone(),
+        // Use the field path as the key field unless key fields are given
+        key_fields: request.key_fields.clone().unwrap_or_else(|| request.field_path.clone()),
+        journey: request.journey.clone(),
+        system: request.system.clone(),
+        depends_on_selector: request.depends_on_selector.clone(),
+        depends_on_condition: request.depends_on_condition.clone(),
+        parameters,
+        logic_type: request.logic_type.clone(),
+        parent_rule_id: request.parent_rule_id.clone(),
+        case_value: request.case_value.clone(),
+        scope: request.scope.clone(),
+    }
+}
+
+// Add the rule described by a request, then its nested branches under it. Returns the new rule's ID.
+fn push_rule_tree(
+    rules: &mut Vec<ValidationRule>,
+    request: &NewRuleRequest,
+    parent_id: Option<&str>,
+    skipped_ids: &HashSet<String>,
+) -> Result<String, DqrError> {
+    let rule_id = match &request.id {
+        Some(rule_id) => {
+            check_rule_id(rule_id)?;
+            if rules.iter().any(|rule| rule.id == *rule_id) || skipped_ids.contains(rule_id) {
+                return Err(DqrError::DuplicateRuleId(rule_id.clone()));
             }
-            
-            // Flush and close
-            writer.flush()?;
-            
-            // Rename temp file to actual file
-            std::fs::rename(temp_path, path)?;
-            
-            Ok(())
-        } else {
-            Err(DqrError::Generic("No rules file path set".to_string()))
+            rule_id.clone()
+        },
+        None => generate_rule_id(rules, skipped_ids),
+    };
+    let mut rule = rule_from_request(&rule_id, request);
+    
+    if let Some(parent_id) = parent_id {
+        if !rule.is_conditional_branch() {
+            return Err(DqrError::InvalidRuleConfig(format!(
+                "branch of rule '{}' for {} must have a then, else, else_if, case or default logic_type",
+                parent_id, request.field_path
+            )));
         }
+        rule.parent_rule_id = parent_id.to_string();
+    }
+    rules.push(rule);
+    
+    for branch in request.branches.iter().flatten() {
+        push_rule_tree(rules, branch, Some(&rule_id), skipped_ids)?;
     }
-}
\ No newline at end of file
+    
+    Ok(rule_id)
+}
+
+// A random (version 4) UUID, so an ID is never handed out twice, even after its rule is
+// deleted or the rules are reloaded
+fn generate_rule_id(rules: &[ValidationRule], skipped_ids: &HashSet<String>) -> String {
+    loop {
+        let rule_id = Uuid::new_v4().to_string();
+        if !rules.iter().any(|rule| rule.id == rule_id) && !skipped_ids.contains(&rule_id) {
+            return rule_id;
+        }
+    }
+}
+
+// Client-chosen IDs end up in rules files and URLs, so keep them to a safe character set
+fn check_rule_id(rule_id: &str) -> Result<(), DqrError> {
+    let valid = !rule_id.is_empty()
+        && rule_id.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'));
+    if !valid {
+        return Err(DqrError::InvalidRuleConfig(format!(
+            "invalid rule ID '{}' (use letters, digits, '_', '-' and '.')",
+            rule_id
+        )));
+    }
+    Ok(())
+}
+
+// Replace a rule in place, keeping its ID; nested branches in the request replace its current ones
+fn replace_rule_tree(
+    rules: &mut Vec<ValidationRule>,
+    rule_id: &str,
+    request: &NewRuleRequest,
+    skipped_ids: &HashSet<String>,
+) -> Result<(), DqrError> {
+    rule_position(rules, rule_id)?;
+    if request.id.as_deref().is_some_and(|id| id != rule_id) {
+        return Err(DqrError::InvalidRuleConfig(format!(
+            "rule ID '{}' in the request doesn't match rule '{}'",
+            request.id.as_deref().unwrap_or_default(), rule_id
+        )));
+    }
+    
+    if let Some(branches) = &request.branches {
+        let descendants = descendant_ids(rules, rule_id);
+        rules.retain(|rule| !descendants.contains(&rule.id));
+        
+        let position = rule_position(rules, rule_id)?;
+        rules[position] = rule_from_request(rule_id, request);
+        for branch in branches {
+            push_rule_tree(rules, branch, Some(rule_id), skipped_ids)?;
+        }
+    } else {
+        let position = rule_position(rules, rule_id)?;
+        rules[position] = rule_from_request(rule_id, request);
+    }
+    
+    Ok(())
+}
+
+// Apply the fields set in a partial update to a rule
+fn apply_changes(rule: &mut ValidationRule, changes: &RuleUpdateRequest) {
+    if let Some(field_path) = &changes.field_path {
+        rule.selector = field_path.clone();
+    }
+    if let Some(validation_type) = &changes.validation_type {
+        rule.condition = validation_type.clone();
+    }
+    if let Some(parameters) = &changes.parameters {
+        // Replacing the parameters keeps the description unless they or the description change it
+        let description = rule.parameters.remove("description");
+        rule.parameters = parameters.clone();
+        if let Some(description) = description {
+            rule.parameters.entry("description".to_string()).or_insert(description);
+        }
+    }
+    if let Some(desc) = &changes.description {
+        rule.parameters.insert("description".to_string(), serde_json::Value::String(desc.clone()));
+    }
+    if let Some(journey) = &changes.journey {
+        rule.journey = journey.clone();
+    }
+    if let Some(system) = &changes.system {
+        rule.system = system.clone();
+    }
+    if let Some(key_fields) = &changes.key_fields {
+        rule.key_fields = key_fields.clone();
+    }
+    if let Some(selector) = &changes.depends_on_selector {
+        rule.depends_on_selector = selector.clone();
+    }
+    if let Some(condition) = &changes.depends_on_condition {
+        rule.depends_on_condition = condition.clone();
+    }
+    if let Some(logic_type) = &changes.logic_type {
+        rule.logic_type = logic_type.clone();
+    }
+    if let Some(parent_rule_id) = &changes.parent_rule_id {
+        rule.parent_rule_id = parent_rule_id.clone();
+    }
+    if let Some(case_value) = &changes.case_value {
+        rule.case_value = case_value.clone();
+    }
+    if let Some(scope) = &changes.scope {
+        rule.scope = scope.clone();
+    }
+}
+
+fn rule_position(rules: &[ValidationRule], rule_id: &str) -> Result<usize, DqrError> {
+    rules
+        .iter()
+        .position(|rule| rule.id == rule_id)
+        .ok_or_else(|| DqrError::RuleNotFound(rule_id.to_string()))
+}
+
+// Remove a rule and every branch rule nested under it
+fn remove_rule_tree(rules: &mut Vec<ValidationRule>, rule_id: &str) -> Result<(), DqrError> {
+    rule_position(rules, rule_id)?;
+    
+    let mut removed = descendant_ids(rules, rule_id);
+    removed.insert(rule_id.to_string());
+    
+    rules.retain(|rule| !removed.contains(&rule.id));
+    Ok(())
+}
+
+// IDs of the branch rules nested under a rule, at any depth
+fn descendant_ids(rules: &[ValidationRule], rule_id: &str) -> HashSet<String> {
+    let mut parents: HashSet<&str> = HashSet::from([rule_id]);
+    let mut descendants = HashSet::new();
+    loop {
+        let before = parents.len();
+        for rule in rules {
+            if rule.is_conditional_branch() && parents.contains(rule.parent_rule_id.as_str()) {
+                parents.insert(&rule.id);
+                descendants.insert(rule.id.clone());
+            }
+        }
+        if parents.len() == before {
+            break;
+        }
+    }
+    descendants.remove(rule_id);
+    descendants
+}
+
+// Compile each row and check the constraints that span rows: unique IDs and
+// branch rules pointing at an existing parent of the right kind. Rows with problems are left out.
+fn compile_rules(
+    rows: Vec<(usize, ValidationRule)>,
+    lists: &ReferenceLists,
+) -> (Vec<Arc<CompiledRule>>, Vec<RuleIssue>) {
+    let mut issues = Vec::new();
+    let mut first_seen: HashMap<String, usize> = HashMap::new();
+    let mut compiled_rules: Vec<(usize, Arc<CompiledRule>)> = Vec::new();
+    
+    for (row, rule) in rows {
+        if let Some(first_row) = first_seen.get(&rule.id) {
+            issues.push(RuleIssue {
+                row,
+                rule_id: rule.id.clone(),
+                message: format!("rule '{}': duplicate rule ID (first defined on row {})", rule.id, first_row),
+            });
+            continue;
+        }
+        first_seen.insert(rule.id.clone(), row);
+        
+        let rule_id = rule.id.clone();
+        match CompiledRule::compile_with_lists(rule, lists) {
+            Ok(compiled) => compiled_rules.push((row, Arc::new(compiled))),
+            Err(e) => issues.push(RuleIssue {
+                row,
+                rule_id,
+                message: match e {
+                    DqrError::InvalidRuleConfig(msg) => msg,
+                    other => other.to_string(),
+                },
+            }),
+        }
+    }
+    
+    // A case rule without a value could never be selected
+    compiled_rules.retain(|(row, compiled)| {
+        let rule = &compiled.rule;
+        if rule.logic_type != ConditionalLogic::Case || !rule.case_value.trim().is_empty() {
+            return true;
+        }
+        issues.push(RuleIssue {
+            row: *row,
+            rule_id: rule.id.clone(),
+            message: format!("rule '{}': \"case\" rules need a case_value", rule.id),
+        });
+        false
+    });
+    
+    // Drop branches whose parent is missing; repeat since dropping a nested
+    // "if"/"else_if" rule orphans its own branches
+    loop {
+        let if_rule_ids: HashSet<String> = compiled_rules
+            .iter()
+            .filter(|(_, compiled)| compiled.rule.is_condition_root())
+            .map(|(_, compiled)| compiled.rule.id.clone())
+            .collect();
+        let switch_rule_ids: HashSet<String> = compiled_rules
+            .iter()
+            .filter(|(_, compiled)| compiled.rule.is_switch())
+            .map(|(_, compiled)| compiled.rule.id.clone())
+            .collect();
+        
+        let before = compiled_rules.len();
+        compiled_rules.retain(|(row, compiled)| {
+            let rule = &compiled.rule;
+            let (parents, parent_kind) = match rule.logic_type {
+                ConditionalLogic::Then | ConditionalLogic::Else | ConditionalLogic::ElseIf => {
+                    (&if_rule_ids, "\"if\" or \"else_if\"")
+                },
+                ConditionalLogic::Case | ConditionalLogic::Default => (&switch_rule_ids, "\"switch\""),
+                _ => return true,
+            };
+            if parents.contains(&rule.parent_rule_id) {
+                return true;
+            }
+            issues.push(RuleIssue {
+                row: *row,
+                rule_id: rule.id.clone(),
+                message: format!(
+                    "rule '{}': parent_rule_id '{}' does not refer to an existing {} rule",
+                    rule.id, rule.parent_rule_id, parent_kind
+                ),
+            });
+            false
+        });
+        
+        if compiled_rules.len() == before {
+            break;
+        }
+    }
+    
+    issues.sort_by_key(|issue| issue.row);
+    (compiled_rules.into_iter().map(|(_, compiled)| compiled).collect(), issues)
+}
@@ -0,0 +1,676 @@
+use std::collections::HashMap;
+use std::io::Write;
+
+use serde_json::json;
+use tempfile::NamedTempFile;
+
+use crate::error::DqrError;
+use crate::models::{BulkOperation, ConditionalLogic, NewRuleRequest, RuleUpdateRequest};
+use crate::rules::{LoadMode, RuleRepository};
+use crate::validation::ValidationEngine;
+
+const HEADER: &str = "id,selector,condition,key_fields,journey,system,depends_on_selector,depends_on_condition,logic_type,parent_rule_id";
+
+// Helper function to write a rules CSV with the standard header
+fn write_rules_file(rows: &[&str]) -> NamedTempFile {
+    let mut file = NamedTempFile::new().unwrap();
+    writeln!(file, "{}", HEADER).unwrap();
+    for row in rows {
+        writeln!(file, "{}", row).unwrap();
+    }
+    file.flush().unwrap();
+    file
+}
+
+#[test]
+fn test_load_from_csv_compiles_rules() {
+    let file = write_rules_file(&["r1,$.name,min_length:3,name,DEFAULT,ALL,,,standard,"]);
+    
+    let mut repo = RuleRepository::new();
+    repo.load_from_csv(file.path()).unwrap();
+    let engine = ValidationEngine::new(repo);
+    let result = engine.validate(&json!({"name": "Jo"}), "DEFAULT", "ALL").unwrap();
+    assert_eq!(result.errors.len(), 1);
+}
+
+#[test]
+fn test_strict_load_reports_every_problem_with_row_numbers() {
+    let file = write_rules_file(&[
+        "r1,$.name,min_length:3,name,DEFAULT,ALL,,,standard,",
+        "r2,$.name,min_lenght:3,name,DEFAULT,ALL,,,standard,",
+        "r3,$.email,regex:[unclosed,email,DEFAULT,ALL,,,standard,",
+        "r4,$.age,min_value:eighteen,age,DEFAULT,ALL,,,standard,",
+        "r5,$.name[,required,name,DEFAULT,ALL,,,standard,",
+        "r6,$.card,required,card,DEFAULT,ALL,,,then,missing_parent",
+        "r1,$.other,required,other,DEFAULT,ALL,,,standard,",
+    ]);
+    
+    let mut repo = RuleRepository::new();
+    let err = repo.load_from_csv(file.path()).unwrap_err();
+    let message = match err {
+        DqrError::InvalidRuleConfig(message) => message,
+        other => panic!("unexpected error: {}", other),
+    };
+    
+    assert!(message.starts_with("6 problem(s)"), "{}", message);
+    assert!(message.contains("row 3: rule 'r2': unknown condition 'min_lenght:3'"), "{}", message);
+    assert!(message.contains("row 4: rule 'r3': invalid regex"), "{}", message);
+    assert!(message.contains("row 5: rule 'r4': invalid numeric limit"), "{}", message);
+    assert!(message.contains("row 6: rule 'r5': invalid JSONPath"), "{}", message);
+    assert!(message.contains("row 7: rule 'r6': parent_rule_id 'missing_parent'"), "{}", message);
+    assert!(message.contains("row 8: rule 'r1': duplicate rule ID (first defined on row 2)"), "{}", message);
+}
+
+#[test]
+fn test_lenient_load_skips_invalid_rows() {
+    use std::fs;
+    
+    let file = write_rules_file(&[
+        "r1,$.name,min_length:3,name,DEFAULT,ALL,,,standard,",
+        "r2,$.name,min_lenght:3,name,DEFAULT,ALL,,,standard,",
+        "check,$.kind,min_lenght:1,kind,DEFAULT,ALL,,,if,",
+        "branch,$.card,required,kind,DEFAULT,ALL,,,then,check",
+    ]);
+    
+    let mut repo = RuleRepository::new();
+    let issues = repo.load_from_csv_with_mode(file.path(), LoadMode::Lenient).unwrap();
+    
+    // The invalid "if" rule also orphans its branch
+    let rows: Vec<usize> = issues.iter().map(|issue| issue.row).collect();
+    assert_eq!(rows, vec![3, 4, 5]);
+    assert_eq!(issues[2].rule_id, "branch");
+    
+    let engine = ValidationEngine::new(repo);
+    let result = engine.validate(&json!({"name": "Jo", "kind": "x"}), "DEFAULT", "ALL").unwrap();
+    assert_eq!(result.errors.len(), 1);
+    assert_eq!(result.errors[0].rule_id, "r1");
+    
+    // Saving would drop the skipped rows from the file, so edits are refused until it
+    // is fixed and reloaded
+    let original = fs::read_to_string(file.path()).unwrap();
+    let result = engine.create_rule(&new_rule_request("$.age", "required"));
+    assert!(matches!(result, Err(DqrError::RulesFileIncomplete(_))), "{:?}", result);
+    assert!(matches!(engine.delete_rule("r1"), Err(DqrError::RulesFileIncomplete(_))));
+    assert_eq!(fs::read_to_string(file.path()).unwrap(), original);
+    assert_eq!(engine.rules().rule_count(), 1);
+    
+    fs::write(file.path(), original.replace("min_lenght", "min_length")).unwrap();
+    assert!(engine.reload_rules().unwrap().is_empty());
+    engine.create_rule(&new_rule_request("$.age", "required")).unwrap();
+    assert_eq!(engine.rules().all_rules().len(), 5);
+}
+
+#[test]
+fn test_failed_load_keeps_existing_rules() {
+    let good = write_rules_file(&["r1,$.name,required,name,DEFAULT,ALL,,,standard,"]);
+    let bad = write_rules_file(&["r1,$.name,requried,name,DEFAULT,ALL,,,standard,"]);
+    
+    let mut repo = RuleRepository::new();
+    repo.load_from_csv(good.path()).unwrap();
+    assert!(repo.load_from_csv(bad.path()).is_err());
+    assert_eq!(repo.get_rules_for_key_field("name").len(), 1);
+}
+
+#[test]
+fn test_reload_rules_swaps_rule_set() {
+    let mut file = write_rules_file(&["r1,$.name,required,name,DEFAULT,ALL,,,standard,"]);
+    
+    let mut repo = RuleRepository::new();
+    repo.load_from_csv(file.path()).unwrap();
+    let engine = ValidationEngine::new(repo);
+    
+    let payload = json!({"name": "Jo"});
+    assert!(engine.validate(&payload, "DEFAULT", "ALL").unwrap().valid);
+    assert_eq!(engine.get_validation_cache_size(), 1);
+    
+    // A valid change is picked up and the cache is cleared
+    writeln!(file, "r2,$.name,min_length:3,name,DEFAULT,ALL,,,standard,").unwrap();
+    file.flush().unwrap();
+    engine.reload_rules().unwrap();
+    assert_eq!(engine.get_validation_cache_size(), 0);
+    assert!(!engine.validate(&payload, "DEFAULT", "ALL").unwrap().valid);
+    
+    // An invalid change is rejected and the previous rules stay active
+    writeln!(file, "r3,$.name,min_lenght:3,name,DEFAULT,ALL,,,standard,").unwrap();
+    file.flush().unwrap();
+    assert!(engine.reload_rules().is_err());
+    let result = engine.validate(&payload, "DEFAULT", "ALL").unwrap();
+    assert_eq!(result.errors.len(), 1);
+    assert_eq!(result.errors[0].rule_id, "r2");
+}
+
+#[test]
+fn test_reference_lists_loaded_alongside_rules() {
+    use std::fs;
+    
+    let dir = tempfile::tempdir().unwrap();
+    fs::create_dir(dir.path().join("lists")).unwrap();
+    fs::write(dir.path().join("lists/currencies.json"), r#"["USD", "EUR", "GBP"]"#).unwrap();
+    fs::write(dir.path().join("lists/countries.csv"), "code,name\nGB,United Kingdom\nFR,France\n").unwrap();
+    
+    let rules_path = dir.path().join("rules.csv");
+    fs::write(&rules_path, format!(
+        "{}\n{}\n{}\n",
+        HEADER,
+        "currency,$.currency,in:@currencies,currency,DEFAULT,ALL,,,standard,",
+        "country,$.country,in_ci:@countries,country,DEFAULT,ALL,,,standard,",
+    )).unwrap();
+    
+    let mut repo = RuleRepository::new();
+    repo.load_from_csv(&rules_path).unwrap();
+    assert_eq!(repo.reference_lists().len(), 2);
+    let engine = ValidationEngine::new(repo);
+    
+    assert!(engine.validate(&json!({"currency": "EUR", "country": "fr"}), "DEFAULT", "ALL").unwrap().valid);
+    let result = engine.validate(&json!({"currency": "JPY", "country": "United Kingdom"}), "DEFAULT", "ALL").unwrap();
+    let mut failed: Vec<_> = result.errors.iter().map(|e| e.rule_id.as_str()).collect();
+    failed.sort();
+    assert_eq!(failed, vec!["country", "currency"]);
+    
+    // Referring to a list that doesn't exist fails the load
+    fs::write(&rules_path, format!("{}\n{}\n", HEADER, "r1,$.code,in:@missing,code,DEFAULT,ALL,,,standard,")).unwrap();
+    let err = RuleRepository::new().load_from_csv(&rules_path).unwrap_err();
+    assert!(err.to_string().contains("unknown reference list 'missing'"), "{}", err);
+}
+
+#[test]
+fn test_switch_rules_loaded_from_csv() {
+    let mut file = NamedTempFile::new().unwrap();
+    writeln!(file, "{},case_value", HEADER).unwrap();
+    for row in [
+        "pay,$.payment.type,required,payment.type,DEFAULT,ALL,,,switch,,",
+        "card,$.payment.card,required,payment.card,DEFAULT,ALL,,,case,pay,card",
+        "other,$.payment.note,required,payment.note,DEFAULT,ALL,,,default,pay,",
+        "no_value,$.payment.x,required,payment.x,DEFAULT,ALL,,,case,pay,",
+        "wrong_parent,$.payment.y,required,payment.y,DEFAULT,ALL,,,case,card,bank",
+    ] {
+        writeln!(file, "{}", row).unwrap();
+    }
+    file.flush().unwrap();
+    
+    let err = RuleRepository::new().load_from_csv(file.path()).unwrap_err().to_string();
+    assert!(err.contains("row 5: rule 'no_value': \"case\" rules need a case_value"), "{}", err);
+    assert!(err.contains("row 6: rule 'wrong_parent': parent_rule_id 'card' does not refer to an existing \"switch\" rule"), "{}", err);
+    
+    let mut repo = RuleRepository::new();
+    repo.load_from_csv_with_mode(file.path(), LoadMode::Lenient).unwrap();
+    let engine = ValidationEngine::new(repo);
+    
+    let result = engine.validate(&json!({"payment": {"type": "card"}}), "DEFAULT", "ALL").unwrap();
+    let failed: Vec<_> = result.errors.iter().map(|e| e.rule_id.as_str()).collect();
+    assert_eq!(failed, vec!["card"]);
+    
+    let result = engine.validate(&json!({"payment": {"type": "cash"}}), "DEFAULT", "ALL").unwrap();
+    let failed: Vec<_> = result.errors.iter().map(|e| e.rule_id.as_str()).collect();
+    assert_eq!(failed, vec!["other"]);
+}
+
+// Helper function to build a rule creation request
+fn new_rule_request(field_path: &str, validation_type: &str) -> NewRuleRequest {
+    NewRuleRequest {
+        field_path: field_path.to_string(),
+        validation_type: validation_type.to_string(),
+        parameters: None,
+        description: None,
+        journey: "DEFAULT".to_string(),
+        system: "ALL".to_string(),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_update_and_patch_keep_rule_id() {
+    let file = write_rules_file(&["r1,$.name,min_length:3,name,DEFAULT,ALL,,,standard,"]);
+    let mut repo = RuleRepository::new();
+    repo.load_from_csv(file.path()).unwrap();
+    
+    repo.update_rule("r1", &new_rule_request("$.code", "regex:^[A-Z]{3}$")).unwrap();
+    let rule = repo.get_rule("r1").unwrap();
+    assert_eq!((rule.selector.as_str(), rule.condition.as_str()), ("$.code", "regex:^[A-Z]{3}$"));
+    assert_eq!(repo.get_rules_for_key_field("$.code").len(), 1);
+    assert!(repo.get_rules_for_key_field("name").is_empty());
+    
+    let changes = RuleUpdateRequest {
+        validation_type: Some("regex:^[A-Z]{2}$".to_string()),
+        description: Some("Country code".to_string()),
+        ..Default::default()
+    };
+    repo.patch_rule("r1", &changes).unwrap();
+    let rule = repo.get_rule("r1").unwrap();
+    assert_eq!((rule.selector.as_str(), rule.condition.as_str()), ("$.code", "regex:^[A-Z]{2}$"));
+    assert_eq!(rule.parameters.get("description"), Some(&json!("Country code")));
+    assert_eq!(repo.get_rules_for_key_field("$.code")[0].rule.condition, "regex:^[A-Z]{2}$");
+    
+    // Parameters are a JSON object, so values keep their commas and types, and the
+    // displayed parameters can be sent back unchanged
+    let changes: RuleUpdateRequest = serde_json::from_value(json!({
+        "parameters": {"message": "{value}, not {limit}", "severity": "warning", "report_errors": true}
+    })).unwrap();
+    repo.patch_rule("r1", &changes).unwrap();
+    let display = repo.get_all_rules_for_display().remove(0);
+    assert_eq!(serde_json::to_value(&display.parameters).unwrap(), json!({
+        "message": "{value}, not {limit}",
+        "severity": "warning",
+        "report_errors": true,
+        "description": "Country code",
+    }));
+    let before = serde_json::to_value(repo.get_rule("r1").unwrap()).unwrap();
+    let changes: RuleUpdateRequest = serde_json::from_value(json!({"parameters": display.parameters})).unwrap();
+    repo.patch_rule("r1", &changes).unwrap();
+    assert_eq!(serde_json::to_value(repo.get_rule("r1").unwrap()).unwrap(), before);
+    
+    // Invalid edits and unknown rules leave the rules untouched
+    let changes = RuleUpdateRequest { validation_type: Some("min_lenght:3".to_string()), ..Default::default() };
+    assert!(matches!(repo.patch_rule("r1", &changes), Err(DqrError::InvalidRuleConfig(_))));
+    assert!(matches!(repo.update_rule("r9", &new_rule_request("$.a", "required")), Err(DqrError::RuleNotFound(_))));
+    assert_eq!(repo.get_rule("r1").unwrap().condition, "regex:^[A-Z]{2}$");
+}
+
+#[test]
+fn test_delete_rule_removes_its_branches() {
+    let file = write_rules_file(&[
+        "is_card,$.type,equals:card,type,DEFAULT,ALL,,,if,",
+        "card_number,$.card,required,card,DEFAULT,ALL,,,then,is_card",
+        "is_bank,$.type,equals:bank,type,DEFAULT,ALL,,,else_if,is_card",
+        "iban,$.iban,required,iban,DEFAULT,ALL,,,then,is_bank",
+        "name,$.name,required,name,DEFAULT,ALL,,,standard,",
+    ]);
+    let mut repo = RuleRepository::new();
+    repo.load_from_csv(file.path()).unwrap();
+    
+    repo.delete_rule("is_card").unwrap();
+    let remaining: Vec<_> = repo.all_rules().iter().map(|rule| rule.id.as_str()).collect();
+    assert_eq!(remaining, vec!["name"]);
+    assert!(repo.conditional_rules.is_empty());
+    assert!(repo.get_rules_for_key_field("type").is_empty());
+    
+    assert!(matches!(repo.delete_rule("is_card"), Err(DqrError::RuleNotFound(_))));
+}
+
+#[test]
+fn test_bulk_operations_are_transactional() {
+    let file = write_rules_file(&[
+        "r1,$.name,required,name,DEFAULT,ALL,,,standard,",
+        "r2,$.age,is_number,age,DEFAULT,ALL,,,standard,",
+    ]);
+    let mut repo = RuleRepository::new();
+    repo.load_from_csv(file.path()).unwrap();
+    
+    // A failing operation rolls back the ones before it
+    let operations = vec![
+        BulkOperation::Delete { id: "r1".to_string() },
+        BulkOperation::Create { rule: new_rule_request("$.email", "min_lenght:3") },
+    ];
+    let err = repo.apply_bulk(&operations).unwrap_err();
+    assert!(err.to_string().contains("unknown condition 'min_lenght:3'"), "{}", err);
+    assert!(repo.get_rule("r1").is_some());
+    
+    let operations = vec![
+        BulkOperation::Delete { id: "r1".to_string() },
+        BulkOperation::Delete { id: "r9".to_string() },
+    ];
+    assert!(matches!(repo.apply_bulk(&operations), Err(DqrError::RuleNotFound(_))));
+    assert!(repo.get_rule("r1").is_some());
+    
+    // Otherwise every operation applies
+    let operations = vec![
+        BulkOperation::Delete { id: "r1".to_string() },
+        BulkOperation::Patch {
+            id: "r2".to_string(),
+            changes: RuleUpdateRequest { validation_type: Some("min_value:18".to_string()), ..Default::default() },
+        },
+        BulkOperation::Create { rule: new_rule_request("$.email", "min_length:3") },
+    ];
+    let created = repo.apply_bulk(&operations).unwrap();
+    assert_eq!(created.len(), 1);
+    assert!(repo.get_rule("r1").is_none());
+    assert_eq!(repo.get_rule("r2").unwrap().condition, "min_value:18");
+    assert_eq!(repo.get_rule(&created[0]).unwrap().selector, "$.email");
+}
+
+#[test]
+fn test_conditional_trees_managed_through_requests() {
+    let engine = ValidationEngine::new(RuleRepository::new());
+    
+    let request: NewRuleRequest = serde_json::from_value(json!({
+        "field_path": "$.payment.type",
+        "validation_type": "equals:card",
+        "key_fields": "payment.type",
+        "logic_type": "if",
+        "branches": [
+            {"field_path": "$.payment.card.number", "validation_type": "required", "logic_type": "then"},
+            {
+                "field_path": "$.payment.type",
+                "validation_type": "equals:bank",
+                "logic_type": "else_if",
+                "branches": [
+                    {"field_path": "$.payment.iban", "validation_type": "required", "logic_type": "then"}
+                ]
+            }
+        ]
+    })).unwrap();
+    let root_id = engine.create_rule(&request).unwrap();
+    
+    // Every rule of the tree is listed, with its conditional attributes
+    let display = engine.get_rules_for_display();
+    assert_eq!(display.len(), 4);
+    assert_eq!(display[0].key_fields, "payment.type");
+    assert_eq!(display.iter().filter(|rule| rule.parent_rule_id == root_id).count(), 2);
+    
+    let failed = |payment: serde_json::Value| {
+        let result = engine.validate(&json!({"payment": payment}), "DEFAULT", "ALL").unwrap();
+        result.errors.iter().map(|e| e.path.clone()).collect::<Vec<_>>()
+    };
+    assert_eq!(failed(json!({"type": "card"})), vec!["$.payment.card.number"]);
+    assert_eq!(failed(json!({"type": "bank"})), vec!["$.payment.iban"]);
+    
+    // Updating with branches replaces the whole subtree
+    let update: NewRuleRequest = serde_json::from_value(json!({
+        "field_path": "$.payment.type",
+        "validation_type": "equals:card",
+        "key_fields": "payment.type",
+        "logic_type": "if",
+        "branches": [
+            {"field_path": "$.payment.note", "validation_type": "required", "logic_type": "else"}
+        ]
+    })).unwrap();
+    engine.update_rule(&root_id, &update).unwrap();
+    assert_eq!(engine.get_rules_for_display().len(), 2);
+    assert!(failed(json!({"type": "card"})).is_empty());
+    assert_eq!(failed(json!({"type": "bank"})), vec!["$.payment.note"]);
+    
+    // Branches must say which branch they belong to
+    let invalid: NewRuleRequest = serde_json::from_value(json!({
+        "field_path": "$.kind",
+        "validation_type": "equals:a",
+        "logic_type": "if",
+        "branches": [{"field_path": "$.a", "validation_type": "required"}]
+    })).unwrap();
+    assert!(matches!(engine.create_rule(&invalid), Err(DqrError::InvalidRuleConfig(_))));
+    assert_eq!(engine.get_rules_for_display().len(), 2);
+}
+
+#[test]
+fn test_rule_ids_are_unique() {
+    let file = write_rules_file(&[
+        "R3,$.name,required,name,DEFAULT,ALL,,,standard,",
+        "R1,$.age,is_number,age,DEFAULT,ALL,,,standard,",
+    ]);
+    let mut repo = RuleRepository::new();
+    repo.load_from_csv(file.path()).unwrap();
+    
+    // Generated IDs are UUIDs, so they can't collide with the IDs in the rules file
+    let created = repo.create_rule(&new_rule_request("$.email", "required")).unwrap();
+    assert_eq!(created.len(), 36);
+    assert!(repo.get_rule(&created).is_some());
+    repo.delete_rule(&created).unwrap();
+    assert_ne!(repo.create_rule(&new_rule_request("$.email", "required")).unwrap(), created);
+    
+    // Client-chosen IDs are used as given, once
+    let request = NewRuleRequest { id: Some("email_format".to_string()), ..new_rule_request("$.email", "regex:@") };
+    assert_eq!(repo.create_rule(&request).unwrap(), "email_format");
+    assert!(matches!(repo.create_rule(&request), Err(DqrError::DuplicateRuleId(id)) if id == "email_format"));
+    
+    let request = NewRuleRequest { id: Some("R1".to_string()), ..new_rule_request("$.email", "required") };
+    assert!(matches!(repo.create_rule(&request), Err(DqrError::DuplicateRuleId(_))));
+    
+    let request = NewRuleRequest { id: Some("has space".to_string()), ..new_rule_request("$.email", "required") };
+    assert!(matches!(repo.create_rule(&request), Err(DqrError::InvalidRuleConfig(_))));
+    
+    // A duplicate within one bulk request rejects the whole request
+    let operations = vec![
+        BulkOperation::Create { rule: NewRuleRequest { id: Some("phone".to_string()), ..new_rule_request("$.phone", "required") } },
+        BulkOperation::Create { rule: NewRuleRequest { id: Some("phone".to_string()), ..new_rule_request("$.phone", "is_string") } },
+    ];
+    assert!(matches!(repo.apply_bulk(&operations), Err(DqrError::DuplicateRuleId(_))));
+    assert!(repo.get_rule("phone").is_none());
+    assert_eq!(repo.rule_count(), 4);
+}
+
+#[test]
+fn test_generated_rule_ids_not_reused_after_reload() {
+    use std::fs;
+    
+    let dir = tempfile::tempdir().unwrap();
+    let rules_path = dir.path().join("rules.csv");
+    fs::write(&rules_path, format!("{}\nR3,$.name,required,name,DEFAULT,ALL,,,standard,\n", HEADER)).unwrap();
+    let mut repo = RuleRepository::new();
+    repo.load_from_csv(&rules_path).unwrap();
+    let engine = ValidationEngine::new(repo);
+    
+    // A deleted rule's ID isn't handed out again, even once the rules are reloaded
+    let created = engine.create_rule(&new_rule_request("$.email", "required")).unwrap();
+    engine.delete_rule(&created).unwrap();
+    engine.reload_rules().unwrap();
+    let recreated = engine.create_rule(&new_rule_request("$.email", "required")).unwrap();
+    assert_ne!(recreated, created);
+    assert_ne!(recreated, "R3");
+    
+    // The IDs of rules skipped by a lenient load stay taken
+    let file = write_rules_file(&[
+        "r1,$.name,required,name,DEFAULT,ALL,,,standard,",
+        "R9,$.name,min_lenght:3,name,DEFAULT,ALL,,,standard,",
+    ]);
+    let mut repo = RuleRepository::new();
+    repo.load_from_csv_with_mode(file.path(), LoadMode::Lenient).unwrap();
+    let request = NewRuleRequest { id: Some("R9".to_string()), ..new_rule_request("$.email", "required") };
+    assert!(matches!(repo.create_rule(&request), Err(DqrError::DuplicateRuleId(id)) if id == "R9"));
+}
+
+#[test]
+fn test_saved_rules_reload_unchanged() {
+    use std::fs;
+    
+    let dir = tempfile::tempdir().unwrap();
+    let rules_path = dir.path().join("rules.csv");
+    fs::write(&rules_path, format!(
+        "{}\n{}\n{}\n{}\n",
+        HEADER,
+        "is_card,$.type,equals:card,type,DEFAULT,ALL,,,if,",
+        "card_number,$.card,\"regex:^[0-9]{16}$\",card,DEFAULT,ALL,,,then,is_card",
+        "line_qty,$.qty,min_value:1,qty,DEFAULT,ALL,$.status,equals:open,standard,",
+    )).unwrap();
+    
+    let mut repo = RuleRepository::new();
+    repo.load_from_csv(&rules_path).unwrap();
+    let engine = ValidationEngine::new(repo);
+    
+    // Edits are saved as they are made, including parameters and every other field
+    let request = NewRuleRequest {
+        id: Some("email".to_string()),
+        parameters: Some(HashMap::from([
+            ("message".to_string(), json!("{path} needs an address, e.g. a@b.c")),
+            ("severity".to_string(), json!("warning")),
+        ])),
+        description: Some("Email, \"work\" address".to_string()),
+        scope: "$.contacts[*]".to_string(),
+        ..new_rule_request("@.email", "required")
+    };
+    engine.create_rule(&request).unwrap();
+    let saved = fs::read_to_string(&rules_path).unwrap();
+    
+    let mut reloaded = RuleRepository::new();
+    reloaded.load_from_csv(&rules_path).unwrap();
+    assert_eq!(
+        serde_json::to_value(reloaded.all_rules()).unwrap(),
+        serde_json::to_value(engine.rules().all_rules()).unwrap(),
+    );
+    let email = reloaded.get_rule("email").unwrap();
+    assert_eq!(email.parameters.get("description"), Some(&json!("Email, \"work\" address")));
+    assert_eq!(email.scope, "$.contacts[*]");
+    
+    // Saving the same rules again produces the same file
+    engine.save_rules_to_file().unwrap();
+    assert_eq!(fs::read_to_string(&rules_path).unwrap(), saved);
+}
+
+#[test]
+fn test_failed_save_keeps_rules_and_file() {
+    use std::fs;
+    
+    let dir = tempfile::tempdir().unwrap();
+    let rules_path = dir.path().join("rules.csv");
+    fs::write(&rules_path, format!("{}\n{}\n", HEADER, "r1,$.name,required,name,DEFAULT,ALL,,,standard,")).unwrap();
+    let original = fs::read_to_string(&rules_path).unwrap();
+    
+    let mut repo = RuleRepository::new();
+    repo.load_from_csv(&rules_path).unwrap();
+    let engine = ValidationEngine::new(repo);
+    
+    // A directory in the way of the temporary file makes every save fail
+    fs::create_dir(dir.path().join("rules.csv.tmp")).unwrap();
+    
+    let err = engine.create_rule(&new_rule_request("$.email", "required")).unwrap_err();
+    assert!(matches!(err, DqrError::IoError(_)), "{}", err);
+    assert!(matches!(engine.delete_rule("r1"), Err(DqrError::IoError(_))));
+    
+    assert_eq!(engine.rules().rule_count(), 1);
+    assert!(engine.get_rule("r1").is_some());
+    assert_eq!(fs::read_to_string(&rules_path).unwrap(), original);
+}
+
+#[test]
+fn test_nested_yaml_rules_file() {
+    use std::fs;
+    
+    let dir = tempfile::tempdir().unwrap();
+    let rules_path = dir.path().join("rules.yaml");
+    fs::write(&rules_path, r#"
+rules:
+  - id: is_card
+    selector: $.payment.type
+    condition: equals:card
+    journey: CHECKOUT
+    then:
+      - id: card_number
+        selector: $.payment.card
+        condition: "regex:^[0-9]{12,19}$"
+        parameters:
+          description: Card number, digits only
+    else:
+      - id: is_bank
+        selector: $.payment.type
+        condition: equals:bank
+        then:
+          - id: iban
+            selector: $.payment.iban
+            condition: required
+      - id: other_method
+        selector: $.payment.reference
+        condition: required
+  - id: tier
+    selector: $.tier
+    condition: is_number
+    journey: CHECKOUT
+    cases:
+      - id: tier_one
+        selector: $.limit
+        condition: "max_value:100"
+        case_value: 1
+    default:
+      - id: tier_other
+        selector: $.limit
+        condition: "max_value:10"
+"#).unwrap();
+    
+    let mut repo = RuleRepository::new();
+    repo.load_from_yaml(&rules_path).unwrap();
+    
+    // Nesting sets logic types and parents; branches inherit the parent's journey
+    let summary: Vec<_> = repo.all_rules()
+        .iter()
+        .map(|rule| (rule.id.as_str(), rule.logic_type.as_str(), rule.parent_rule_id.as_str(), rule.journey.as_str()))
+        .collect();
+    assert_eq!(summary, vec![
+        ("is_card", "if", "", "CHECKOUT"),
+        ("card_number", "then", "is_card", "CHECKOUT"),
+        ("is_bank", "else_if", "is_card", "CHECKOUT"),
+        ("iban", "then", "is_bank", "CHECKOUT"),
+        ("other_method", "else", "is_card", "CHECKOUT"),
+        ("tier", "switch", "", "CHECKOUT"),
+        ("tier_one", "case", "tier", "CHECKOUT"),
+        ("tier_other", "default", "tier", "CHECKOUT"),
+    ]);
+    assert_eq!(repo.get_rule("tier_one").unwrap().case_value, "1");
+    assert_eq!(repo.get_rule("card_number").unwrap().key_fields, "$.payment.card");
+    
+    let engine = ValidationEngine::new(repo);
+    let payload = json!({"payment": {"type": "card", "card": "12ab"}, "tier": 1, "limit": 50});
+    let result = engine.validate(&payload, "CHECKOUT", "ALL").unwrap();
+    let failed: Vec<_> = result.errors.iter().map(|e| e.rule_id.as_str()).collect();
+    assert_eq!(failed, vec!["card_number"]);
+    
+    // A rule nested in the wrong branch is reported with its position in the file
+    fs::write(&rules_path, r#"
+rules:
+  - id: is_card
+    selector: $.payment.type
+    condition: equals:card
+    then:
+      - id: misplaced
+        selector: $.payment.card
+        condition: required
+        logic_type: case
+"#).unwrap();
+    let err = RuleRepository::new().load_from_yaml(&rules_path).unwrap_err();
+    assert!(err.to_string().contains("row 2: rule 'misplaced': a \"case\" rule can't be nested under \"then\""), "{}", err);
+    
+    // Unknown keys are rejected rather than ignored
+    fs::write(&rules_path, "rules:\n  - id: r1\n    selector: $.name\n    condition: required\n    thn: []\n").unwrap();
+    assert!(matches!(RuleRepository::new().load_from_yaml(&rules_path), Err(DqrError::YamlError(_))));
+}
+
+#[test]
+fn test_rules_convert_between_formats() {
+    use std::fs;
+    
+    let dir = tempfile::tempdir().unwrap();
+    let csv_path = dir.path().join("rules.csv");
+    fs::write(&csv_path, format!(
+        "{},parameters,case_value,scope\n{}\n{}\n{}\n{}\n{}\n{}\n",
+        HEADER,
+        "is_card,$.type,equals:card,type,DEFAULT,ALL,,,if,,,,",
+        "card_number,$.card,\"regex:^[0-9]{12,19}$\",card,DEFAULT,ALL,,,then,is_card,\"{\"\"description\"\":\"\"Card, digits only\"\"}\",,",
+        "is_bank,$.type,equals:bank,type,DEFAULT,ALL,,,else_if,is_card,,,",
+        "tier,$.tier,is_number,tier,ONBOARDING,WEB,,,switch,,,,",
+        "tier_one,@.limit,max_value:100,limit,ONBOARDING,ALL,$.status,equals:open,case,tier,,1,$.accounts[*]",
+        "name,$.name,required,\"name,full_name\",DEFAULT,ALL,,,standard,,,,",
+    )).unwrap();
+    
+    let mut repo = RuleRepository::new();
+    repo.load_file_with_mode(&csv_path, LoadMode::Strict).unwrap();
+    let original = serde_json::to_value(repo.all_rules()).unwrap();
+    
+    for name in ["rules.json", "rules.yaml", "rules.yml", "copy.csv"] {
+        repo.export_rules(dir.path().join(name)).unwrap();
+        let mut converted = RuleRepository::new();
+        converted.load_file_with_mode(dir.path().join(name), LoadMode::Strict).unwrap();
+        assert_eq!(serde_json::to_value(converted.all_rules()).unwrap(), original, "{}", name);
+    }
+    
+    // Edits to a JSON rules file are saved as JSON, with branches nested under their rule
+    let json_path = dir.path().join("rules.json");
+    let mut repo = RuleRepository::new();
+    repo.load_from_json(&json_path).unwrap();
+    let engine = ValidationEngine::new(repo);
+    let request = NewRuleRequest {
+        id: Some("card_expiry".to_string()),
+        logic_type: ConditionalLogic::Then,
+        parent_rule_id: "is_card".to_string(),
+        ..new_rule_request("$.expiry", "required")
+    };
+    engine.create_rule(&request).unwrap();
+    
+    let saved: serde_json::Value = serde_json::from_str(&fs::read_to_string(&json_path).unwrap()).unwrap();
+    let then_ids: Vec<_> = saved["rules"][0]["then"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|rule| rule["id"].as_str().unwrap())
+        .collect();
+    assert_eq!(then_ids, vec!["card_number", "card_expiry"]);
+    assert_eq!(saved["rules"][0]["else"][0]["logic_type"], json!("else_if"));
+    
+    engine.reload_rules().unwrap();
+    assert_eq!(engine.rules().rule_count(), 7);
+}
@@ -0,0 +1,47 @@
+use serde_json::Value;
+use serde_json_path::{JsonPath, LocatedNodeList, NodeList};
+
+use crate::compiled::parse_selector;
+use crate::condition::CheckContext;
+use crate::error::DqrError;
+
+// A JSONPath selector that is either absolute ("$...", resolved against the document)
+// or relative ("@...", resolved against the element selected by the rule's scope)
+#[derive(Debug, Clone)]
+pub struct Selector {
+    path: JsonPath,
+    relative: bool,
+}
+
+impl Selector {
+    pub fn parse(selector: &str) -> Result<Self, DqrError> {
+        let selector = selector.trim();
+        match selector.strip_prefix('@') {
+            Some(rest) => {
+                let path = JsonPath::parse(&format!("${}", rest)).map_err(|e| {
+                    DqrError::InvalidRuleConfig(format!("invalid JSONPath '{}': {}", selector, e))
+                })?;
+                Ok(Selector { path, relative: true })
+            },
+            None => Ok(Selector { path: parse_selector(selector)?, relative: false }),
+        }
+    }
+
+    pub fn is_relative(&self) -> bool {
+        self.relative
+    }
+
+    // The value the selector is resolved against
+    pub fn target<'b>(&self, ctx: &CheckContext<'b>) -> &'b Value {
+        if self.relative { ctx.scope } else { ctx.root }
+    }
+
+    pub fn query<'b>(&self, ctx: &CheckContext<'b>) -> NodeList<'b> {
+        self.path.query(self.target(ctx))
+    }
+
+    // Locations are relative to the target: the scope element for relative selectors
+    pub fn query_located<'b>(&self, ctx: &CheckContext<'b>) -> LocatedNodeList<'b> {
+        self.path.query_located(self.target(ctx))
+    }
+}
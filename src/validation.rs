@@ -1,532 +1,767 @@
+use arc_swap::ArcSwap;
 use serde_json::Value;
-use regex::Regex;
-use std::collections::HashMap;
+use serde_json_path::{NormalizedPath, PathElement};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, MutexGuard};
 use std::hash::{Hash, Hasher};
 use std::collections::hash_map::DefaultHasher;
 
+use crate::cache::{BoundedCache, CacheConfig, CacheStats};
+use crate::compiled::CompiledRule;
+use crate::condition::{split_guard, CheckContext};
+use crate::dates::{Clock, SystemClock};
 use crate::error::DqrError;
-use crate::models::{NewRuleRequest, RuleDisplay, ValidationError, ValidationRule, ValidationResponse};
-use crate::rules::RuleRepository;
+use crate::models::{
+    BulkOperation, NewRuleRequest, RuleDisplay, RuleUpdateRequest, ValidationError, ValidationOptions,
+    ValidationRule, ValidationResponse,
+};
+use crate::rules::{applies_to_journey_system, RuleIssue, RuleRepository};
 
-#[derive(Clone)]
+// Rules selected for one journey/system combination, shared between cache hits
+type RuleSelection = Arc<Vec<Arc<CompiledRule>>>;
+
+// A published rule set. The generation tags cache entries computed from it, so
+// entries written by a validation that raced a rule change can never be served later.
+struct RuleSnapshot {
+    generation: u64,
+    repository: Arc<RuleRepository>,
+}
+
+// The engine is shared between request handlers without an outer lock.
+// Validations read an immutable snapshot of the rules; rule edits build a new
+// snapshot and swap it in atomically, so readers never wait on writers.
 pub struct ValidationEngine {
-    rule_repository: RuleRepository,
-    // Cache for validation results
-    validation_cache: HashMap<u64, ValidationResponse>,
+    snapshot: ArcSwap<RuleSnapshot>,
+    // Serializes rule edits so concurrent writers don't lose each other's changes
+    write_lock: Mutex<()>,
+    // Rules selected per (generation, journey, system)
+    rule_selection_cache: BoundedCache<(u64, String, String), RuleSelection>,
+    // Validation results per (generation, hash of payload, journey, system and options)
+    validation_cache: BoundedCache<(u64, u64), ValidationResponse>,
+    // Current time for relative date conditions
+    clock: Arc<dyn Clock>,
 }
 
 impl ValidationEngine {
     pub fn new(rule_repository: RuleRepository) -> Self {
+        Self::with_cache_config(rule_repository, CacheConfig::default())
+    }
+    
+    pub fn with_cache_config(rule_repository: RuleRepository, cache_config: CacheConfig) -> Self {
         ValidationEngine { 
-            rule_repository,
-            validation_cache: HashMap::new(),
+            snapshot: ArcSwap::from_pointee(RuleSnapshot {
+                generation: 0,
+                repository: Arc::new(rule_repository),
+            }),
+            write_lock: Mutex::new(()),
+            rule_selection_cache: BoundedCache::new(cache_config),
+            validation_cache: BoundedCache::new(cache_config),
+            clock: Arc::new(SystemClock),
         }
     }
     
+    // Replace the clock used by relative date conditions (e.g. with a fixed clock in tests)
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+    
+    // Get the current rules snapshot
+    pub fn rules(&self) -> Arc<RuleRepository> {
+        self.snapshot.load().repository.clone()
+    }
+    
+    // Acquire the writer lock; it guards no data, so a poisoned lock is still usable
+    fn lock_writes(&self) -> MutexGuard<'_, ()> {
+        self.write_lock.lock().unwrap_or_else(|e| e.into_inner())
+    }
+    
+    // Apply an edit to a copy of the current rules and publish it as the new snapshot.
+    // If the edit fails, the current snapshot is left untouched.
+    fn update_rules<T>(
+        &self,
+        edit: impl FnOnce(&mut RuleRepository) -> Result<T, DqrError>,
+    ) -> Result<T, DqrError> {
+        let _guard = self.lock_writes();
+        
+        let current = self.snapshot.load_full();
+        let mut repository = RuleRepository::clone(&current.repository);
+        let result = edit(&mut repository)?;
+        self.snapshot.store(Arc::new(RuleSnapshot {
+            generation: current.generation + 1,
+            repository: Arc::new(repository),
+        }));
+        
+        // Cached entries for the old generation can no longer be hit; free them
+        self.clear_caches();
+        
+        Ok(result)
+    }
+    
+    // Apply a management edit like update_rules, writing the result to the rules file (if
+    // the rules came from one) before publishing it. A failed write leaves both the file and
+    // the active rules unchanged, so an edit is never reported as done and then lost.
+    fn edit_rules<T>(
+        &self,
+        edit: impl FnOnce(&mut RuleRepository) -> Result<T, DqrError>,
+    ) -> Result<T, DqrError> {
+        self.update_rules(|repository| {
+            let result = edit(repository)?;
+            if repository.rules_file_path().is_some() {
+                repository.save_rules_to_file()?;
+            }
+            Ok(result)
+        })
+    }
+    
     // Rule management methods
     
     // Get all rules for display
     pub fn get_rules_for_display(&self) -> Vec<RuleDisplay> {
-        self.rule_repository.get_all_rules_for_display()
+        self.rules().get_all_rules_for_display()
+    }
+    
+    // Get a single rule by ID
+    pub fn get_rule(&self, rule_id: &str) -> Option<ValidationRule> {
+        self.rules().get_rule(rule_id).cloned()
     }
     
     // Create a new rule
-    pub fn create_rule(&mut self, req: &NewRuleRequest) -> Result<String, DqrError> {
-        self.rule_repository.create_rule(req)
+    pub fn create_rule(&self, req: &NewRuleRequest) -> Result<String, DqrError> {
+        self.edit_rules(|repository| repository.create_rule(req))
+    }
+    
+    // Replace a rule, keeping its ID
+    pub fn update_rule(&self, rule_id: &str, req: &NewRuleRequest) -> Result<(), DqrError> {
+        self.edit_rules(|repository| repository.update_rule(rule_id, req))
+    }
+    
+    // Change some fields of a rule
+    pub fn patch_rule(&self, rule_id: &str, changes: &RuleUpdateRequest) -> Result<(), DqrError> {
+        self.edit_rules(|repository| repository.patch_rule(rule_id, changes))
+    }
+    
+    // Apply several rule changes as one; the rules are unchanged if any of them fails
+    pub fn apply_bulk(&self, operations: &[BulkOperation]) -> Result<Vec<String>, DqrError> {
+        self.edit_rules(|repository| repository.apply_bulk(operations))
     }
     
     // Delete a rule
-    pub fn delete_rule(&mut self, rule_id: &str) -> Result<(), DqrError> {
-        self.rule_repository.delete_rule(rule_id)
+    pub fn delete_rule(&self, rule_id: &str) -> Result<(), DqrError> {
+        self.edit_rules(|repository| repository.delete_rule(rule_id))
     }
     
-    // Save rules to file
+    // Save rules to file (holding the writer lock so saves can't interleave with edits)
     pub fn save_rules_to_file(&self) -> Result<(), DqrError> {
-        self.rule_repository.save_rules_to_file()
+        let _guard = self.lock_writes();
+        self.rules().save_rules_to_file()
+    }
+    
+    // Get the path of the rules file backing this engine
+    pub fn rules_file_path(&self) -> Option<PathBuf> {
+        self.rules().rules_file_path().map(|p| p.to_path_buf())
+    }
+    
+    // Reload the rules file into a fresh repository and swap it in.
+    // If the file fails validation the current rules are kept.
+    pub fn reload_rules(&self) -> Result<Vec<RuleIssue>, DqrError> {
+        self.update_rules(|current| {
+            let path = current
+                .rules_file_path()
+                .ok_or_else(|| DqrError::Generic("No rules file path set".to_string()))?
+                .to_path_buf();
+            
+            let mut repository = RuleRepository::new();
+            if let Some(lists_path) = current.lists_path() {
+                repository.set_lists_path(lists_path);
+            }
+            let issues = repository.load_with_format(&path, current.rules_file_format(), current.load_mode())?;
+            
+            log::info!("Reloaded {} rules from {}", repository.rule_count(), path.display());
+            *current = repository;
+            
+            Ok(issues)
+        })
     }
     
     // Helper method to calculate a hash for the validation inputs
-    fn calculate_hash(&self, json: &Value, journey: &str, system: &str) -> u64 {
+    fn calculate_hash(&self, json: &Value, journey: &str, system: &str, options: &ValidationOptions) -> u64 {
         let mut hasher = DefaultHasher::new();
         
         // Hash the JSON data
         let json_str = json.to_string();
         json_str.hash(&mut hasher);
         
-        // Hash the journey and system
+        // Hash the journey, system and options
         journey.hash(&mut hasher);
         system.hash(&mut hasher);
+        options.full_evaluation.hash(&mut hasher);
+        
+        // Relative date bounds resolve per day, so results can't be reused across days
+        self.clock.now().date_naive().hash(&mut hasher);
         
         hasher.finish()
     }
     
+    // Clear the rule selection and validation caches
+    pub fn clear_caches(&self) {
+        self.rule_selection_cache.clear();
+        self.validation_cache.clear();
+    }
+    
     // Clear the validation cache
-    pub fn clear_validation_cache(&mut self) {
+    pub fn clear_validation_cache(&self) {
         self.validation_cache.clear();
     }
     
     // Get validation cache size
     pub fn get_validation_cache_size(&self) -> usize {
-        self.validation_cache.len()
+        self.validation_cache.len() as usize
     }
     
     // Get journey system cache size
     pub fn get_journey_system_cache_size(&self) -> usize {
-        self.rule_repository.get_journey_system_cache_size()
+        self.rule_selection_cache.len() as usize
+    }
+    
+    // Get size and hit/miss counters of the validation result cache
+    pub fn validation_cache_stats(&self) -> CacheStats {
+        self.validation_cache.stats()
+    }
+    
+    // Get size and hit/miss counters of the journey/system rule selection cache
+    pub fn journey_system_cache_stats(&self) -> CacheStats {
+        self.rule_selection_cache.stats()
     }
 
     // Get the relevant rules for a specific journey and system combination
-    pub fn get_rules_for_journey_system(&self, journey: &str, system: &str) -> Vec<ValidationRule> {
-        // Use the cached method from repository
-        self.rule_repository.get_rules_for_journey_system(journey, system)
+    pub fn get_rules_for_journey_system(&self, journey: &str, system: &str) -> Vec<Arc<CompiledRule>> {
+        let snapshot = self.snapshot.load();
+        self.rules_for_journey_system(&snapshot, journey, system).to_vec()
+    }
+    
+    // Get the rules that would be evaluated for a payload
+    pub fn get_rules_for_payload(
+        &self,
+        json: &Value,
+        journey: &str,
+        system: &str,
+        options: &ValidationOptions,
+    ) -> Vec<Arc<CompiledRule>> {
+        let snapshot = self.snapshot.load();
+        self.select_rules(&snapshot, json, journey, system, options)
+    }
+    
+    // Find the rules triggered by the payload's key fields through the key field index and
+    // keep those for the journey and system; a full evaluation takes every rule for them
+    fn select_rules(
+        &self,
+        snapshot: &RuleSnapshot,
+        json: &Value,
+        journey: &str,
+        system: &str,
+        options: &ValidationOptions,
+    ) -> Vec<Arc<CompiledRule>> {
+        if options.full_evaluation {
+            return self.rules_for_journey_system(snapshot, journey, system).to_vec();
+        }
+        
+        let mut rules = snapshot.repository.get_rules_for_payload(json);
+        rules.retain(|compiled| applies_to_journey_system(&compiled.rule, journey, system));
+        rules
+    }
+    
+    // Look up the rules for a journey/system in the cache, filling it on a miss
+    fn rules_for_journey_system(&self, snapshot: &RuleSnapshot, journey: &str, system: &str) -> RuleSelection {
+        let cache_key = (snapshot.generation, journey.to_string(), system.to_string());
+        if let Some(cached_rules) = self.rule_selection_cache.get(&cache_key) {
+            return cached_rules;
+        }
+        
+        let rules = Arc::new(snapshot.repository.get_rules_for_journey_system(journey, system));
+        self.rule_selection_cache.insert(cache_key, rules.clone());
+        rules
     }
 
+    // Validate against the rules whose key fields are present in the payload
     pub fn validate(
         &self, 
         json: &Value, 
         journey: &str, 
         system: &str
     ) -> Result<ValidationResponse, DqrError> {
+        self.validate_with_options(json, journey, system, &ValidationOptions::default())
+    }
+    
+    pub fn validate_with_options(
+        &self, 
+        json: &Value, 
+        journey: &str, 
+        system: &str,
+        options: &ValidationOptions,
+    ) -> Result<ValidationResponse, DqrError> {
+        // Use one snapshot for the whole validation so a concurrent rule change
+        // can't produce a mix of old and new rules
+        let snapshot = self.snapshot.load_full();
+        
         // Calculate hash for cache lookup
-        let cache_key = self.calculate_hash(json, journey, system);
+        let cache_key = (snapshot.generation, self.calculate_hash(json, journey, system, options));
         
         // Check cache first
         if let Some(cached_result) = self.validation_cache.get(&cache_key) {
-            return Ok(cached_result.clone());
-        }
-        
-        // Get applicable rules for this journey and system
-        let rules = self.get_rules_for_journey_system(journey, system);
-        
-        // Apply validation rules
-        let mut errors = Vec::new();
-        
-        for rule in rules {
-            // Skip conditional branch rules here - they'll be processed by their parent
-            if rule.is_conditional_branch() {
-                continue;
-            }
-            
-            // Apply the rule
-            match self.apply_rule(json, &rule) {
-                Ok((condition_result, rule_errors)) => {
-                    // Add any errors from this rule
-                    errors.extend(rule_errors);
-                    
-                    // If this is a conditional root rule, process its branches
-                    if rule.is_condition_root() {
-                        let conditional_errors = self.process_conditional_rules(json, &rule.id, condition_result);
-                        errors.extend(conditional_errors);
-                    }
-                },
-                Err(e) => {
-                    log::error!("Error applying rule {}: {}", rule.id, e);
-                }
-            }
+            return Ok(cached_result);
         }
         
-        // Create the response
-        let response = if errors.is_empty() {
-            ValidationResponse::success()
-        } else {
-            ValidationResponse::failure(errors)
-        };
+        let response = self.run_rules(&snapshot, json, journey, system, options);
         
-        // Note: We can't modify the cache here in a &self method
-        // Cache will be updated separately with an explicit update method in the mutable version
+        // Update the cache
+        self.validation_cache.insert(cache_key, response.clone());
         
         Ok(response)
     }
     
-    // Mutable version of validate that updates caches
-    pub fn validate_mut(
-        &mut self, 
-        json: &Value, 
-        journey: &str, 
-        system: &str
-    ) -> Result<ValidationResponse, DqrError> {
-        // Calculate hash for cache lookup
-        let cache_key = self.calculate_hash(json, journey, system);
+    // Apply every applicable rule from the snapshot
+    fn run_rules(
+        &self,
+        snapshot: &RuleSnapshot,
+        json: &Value,
+        journey: &str,
+        system: &str,
+        options: &ValidationOptions,
+    ) -> ValidationResponse {
+        let repository = &snapshot.repository;
         
-        // Check cache first
-        if let Some(cached_result) = self.validation_cache.get(&cache_key) {
-            return Ok(cached_result.clone());
+        // Get applicable rules for this journey and system
+        let rules = self.select_rules(snapshot, json, journey, system, options);
+        for compiled in &rules {
+            let rule = &compiled.rule;
+            log::info!("Rule {} will be applied for {}:{}: [{}] {}", 
+                      rule.id, journey, system, rule.selector, rule.condition);
         }
         
-        // Get applicable rules and update rule cache
-        let rules = self.rule_repository.get_rules_for_journey_system(journey, system);
-        
         // Apply validation rules
         let mut errors = Vec::new();
         
-        for rule in &rules {
+        for compiled in rules.iter() {
+            let rule = &compiled.rule;
+            
             // Skip conditional branch rules here - they'll be processed by their parent
             if rule.is_conditional_branch() {
                 continue;
             }
             
-            // Apply the rule
-            match self.apply_rule(json, rule) {
-                Ok((condition_result, rule_errors)) => {
-                    // Add any errors from this rule
-                    errors.extend(rule_errors);
-                    
-                    // If this is a conditional root rule, process its branches
-                    if rule.is_condition_root() {
-                        let conditional_errors = self.process_conditional_rules(json, &rule.id, condition_result);
-                        errors.extend(conditional_errors);
-                    }
-                },
-                Err(e) => {
-                    log::error!("Error applying rule {}: {}", rule.id, e);
-                }
-            }
+            // Apply the rule, then any branches it selects
+            errors.extend(self.apply_with_branches(repository, json, compiled, &Scope::root(json)));
         }
         
         // Create the response
-        let response = if errors.is_empty() {
+        if errors.is_empty() {
             ValidationResponse::success()
         } else {
-            ValidationResponse::failure(errors)
-        };
+            ValidationResponse::from_errors(errors)
+        }
+    }
+    
+    // Compile and apply a single rule (mainly useful for testing individual rules)
+    pub fn apply_rule(&self, json: &Value, rule: &ValidationRule) -> Result<(bool, Vec<ValidationError>), DqrError> {
+        let compiled = CompiledRule::compile(rule.clone())?;
+        Ok(self.apply_compiled_rule(json, &compiled))
+    }
+    
+    // Apply a rule against the whole document, or against each element of its scope
+    pub fn apply_compiled_rule(&self, json: &Value, compiled: &CompiledRule) -> (bool, Vec<ValidationError>) {
+        let mut passed = true;
+        let mut errors = Vec::new();
         
-        // Update the cache
-        self.validation_cache.insert(cache_key, response.clone());
+        for scope in self.scopes_for(json, compiled, &Scope::root(json)) {
+            let (result, scope_errors) = self.apply_in_scope(json, compiled, &scope);
+            passed &= result;
+            errors.extend(scope_errors);
+        }
         
-        Ok(response)
+        (passed, errors)
     }
     
-    pub fn apply_rule(&self, json: &Value, rule: &ValidationRule) -> Result<(bool, Vec<ValidationError>), DqrError> {
+    // The scopes a rule is evaluated in: each element selected by its scope selector
+    // (relative to the current scope for "@" selectors), or just the current scope
+    fn scopes_for<'a>(&self, json: &'a Value, compiled: &CompiledRule, current: &Scope<'a>) -> Vec<Scope<'a>> {
+        let Some(scope_selector) = &compiled.scope else {
+            return vec![current.clone()];
+        };
+        
+        let ctx = CheckContext { root: json, scope: current.value, now: self.clock.now() };
+        scope_selector
+            .query_located(&ctx)
+            .into_iter()
+            .map(|node| {
+                let (path, pointer) = current.locate(scope_selector.is_relative(), node.location());
+                Scope { value: node.node(), path, pointer }
+            })
+            .collect()
+    }
+    
+    // Apply a rule within a single scope
+    fn apply_in_scope(&self, json: &Value, compiled: &CompiledRule, scope: &Scope) -> (bool, Vec<ValidationError>) {
+        let ctx = CheckContext { root: json, scope: scope.value, now: self.clock.now() };
+        
         // Check dependency condition first if it exists
-        if !rule.depends_on_selector.is_empty() && !rule.depends_on_condition.is_empty() {
-            // Apply the depends_on selector
-            let depends_selection = match jsonpath_lib::select(json, &rule.depends_on_selector) {
-                Ok(selection) => selection,
-                Err(_) => {
-                    // If we can't evaluate the dependency, skip this rule
-                    return Ok((true, Vec::new()));
-                }
-            };
-            
-            // Check if any selected value matches the dependency condition
-            let mut dependency_met = false;
-            for depends_value in depends_selection.iter() {
-                // Check the dependency condition
-                if rule.depends_on_condition.starts_with("equals:") {
-                    if let Some(expected_value) = rule.depends_on_condition.strip_prefix("equals:") {
-                        match depends_value {
-                            Value::String(s) => {
-                                if s == expected_value {
-                                    dependency_met = true;
-                                    break;
-                                }
-                            },
-                            Value::Number(n) => {
-                                if let Ok(num) = expected_value.parse::<f64>() {
-                                    if let Some(val_num) = n.as_f64() {
-                                        if (val_num - num).abs() < f64::EPSILON {
-                                            dependency_met = true;
-                                            break;
-                                        }
-                                    }
-                                }
-                            },
-                            Value::Bool(b) => {
-                                if let Ok(expected_bool) = expected_value.parse::<bool>() {
-                                    if *b == expected_bool {
-                                        dependency_met = true;
-                                        break;
-                                    }
-                                }
-                            },
-                            _ => {}
-                        }
-                    }
-                } else if rule.depends_on_condition == "not_empty" {
-                    // Check if the value is not null and not an empty string
-                    match depends_value {
-                        Value::String(s) => {
-                            if !s.is_empty() {
-                                dependency_met = true;
-                                break;
-                            }
-                        },
-                        Value::Array(arr) => {
-                            if !arr.is_empty() {
-                                dependency_met = true;
-                                break;
-                            }
-                        },
-                        Value::Object(obj) => {
-                            if !obj.is_empty() {
-                                dependency_met = true;
-                                break;
-                            }
-                        },
-                        Value::Number(_) | Value::Bool(_) => {
-                            // Numbers and booleans are never empty
-                            dependency_met = true;
-                            break;
-                        },
-                        Value::Null => {
-                            // Null is considered empty
-                        }
-                    }
-                }
-                // Add more dependency condition types here if needed
-            }
-            
+        if let Some(dependency) = &compiled.dependency {
             // If the dependency condition is not met, skip this rule
-            if !dependency_met {
-                return Ok((true, Vec::new()));
+            if !dependency.is_met(&ctx) {
+                return (true, Vec::new());
             }
         }
         
         // Apply JSON path selector to find the values to validate
-        let selection = jsonpath_lib::select(json, &rule.selector)
-            .map_err(|_| DqrError::JsonPathError(format!("Invalid JSONPath: {}", rule.selector)))?;
-        
-        // If no values match and condition is required, that's an error
-        if rule.condition == "required" && selection.is_empty() {
-            return Ok((false, vec![ValidationError {
-                path: rule.selector.clone(),
-                rule_id: rule.id.clone(),
-            }]));
+        let selection = compiled.selector.query_located(&ctx);
+        
+        // Aggregate conditions judge the selection as a whole and report against the selector
+        if compiled.condition.is_aggregate() {
+            let values: Vec<&Value> = selection.iter().map(|node| node.node()).collect();
+            let (aggregate, failure) = compiled.condition.check_all(&values, &ctx);
+            return match failure {
+                Some(failure) => {
+                    let (path, pointer) = scope.locate_selector(compiled);
+                    let error = self.build_error(compiled, path, pointer, Some(&aggregate), failure.limit.as_deref());
+                    (false, vec![error])
+                },
+                None => (true, Vec::new()),
+            };
         }
         
-        // If selection is empty for non-required conditions, consider validation passed
-        if selection.is_empty() && rule.condition != "required" {
-            return Ok((true, Vec::new()));
+        if selection.is_empty() {
+            // If no values match and condition is required, that's an error
+            if compiled.condition.requires_presence(&ctx) {
+                let (path, pointer) = scope.locate_selector(compiled);
+                let error = self.build_error(compiled, path, pointer, None, None);
+                return (false, vec![error]);
+            }
+            
+            // Otherwise consider validation passed
+            return (true, Vec::new());
         }
         
         // Process each condition
         let mut errors = Vec::new();
-        let mut condition_passed = true;
         
-        for (idx, value) in selection.iter().enumerate() {
-            let path = format!("{} (item {})", rule.selector, idx);
-            
-            match rule.condition.as_str() {
-                "required" => {
-                    if value.is_null() || (value.is_string() && value.as_str().unwrap().is_empty()) {
-                        errors.push(ValidationError {
-                            path: path.clone(),
-                            rule_id: rule.id.clone(),
-                        });
-                    }
-                },
-                "is_number" => {
-                    if !value.is_number() {
-                        errors.push(ValidationError {
-                            path: path.clone(),
-                            rule_id: rule.id.clone(),
-                        });
-                    }
-                },
-                "is_string" => {
-                    if !value.is_string() {
-                        errors.push(ValidationError {
-                            path: path.clone(),
-                            rule_id: rule.id.clone(),
-                        });
-                    }
-                },
-                "is_boolean" => {
-                    if !value.is_boolean() {
-                        errors.push(ValidationError {
-                            path: path.clone(),
-                            rule_id: rule.id.clone(),
-                        });
-                    }
-                },
-                "is_array" => {
-                    if !value.is_array() {
-                        errors.push(ValidationError {
-                            path: path.clone(),
-                            rule_id: rule.id.clone(),
-                        });
-                    }
-                },
-                "is_object" => {
-                    if !value.is_object() {
-                        errors.push(ValidationError {
-                            path: path.clone(),
-                            rule_id: rule.id.clone(),
-                        });
-                    }
-                },
-                condition if condition.starts_with("min_length:") => {
-                    if let Some(min_length_str) = condition.strip_prefix("min_length:") {
-                        if let Ok(min_length) = min_length_str.parse::<usize>() {
-                            if !value.is_string() || value.as_str().unwrap().len() < min_length {
-                                errors.push(ValidationError {
-                                    path: path.clone(),
-                                    rule_id: rule.id.clone(),
-                                });
-                            }
-                        }
-                    }
-                },
-                "min_length_when_single" => {
-                    // Special case for single applicant
-                    if let Ok(numbers_selection) = jsonpath_lib::select(json, "$.application.individuals.number") {
-                        if let Some(numbers) = numbers_selection.first() {
-                            if numbers.is_number() && numbers.as_i64() == Some(1) {
-                                if !value.is_string() || value.as_str().unwrap().len() <= 1 {
-                                    errors.push(ValidationError {
-                                        path: path.clone(),
-                                        rule_id: rule.id.clone(),
-                                    });
-                                }
-                            }
-                        }
-                    }
-                },
-                condition if condition.starts_with("max_length:") => {
-                    if let Some(max_length_str) = condition.strip_prefix("max_length:") {
-                        if let Ok(max_length) = max_length_str.parse::<usize>() {
-                            if !value.is_string() || value.as_str().unwrap().len() > max_length {
-                                errors.push(ValidationError {
-                                    path: path.clone(),
-                                    rule_id: rule.id.clone(),
-                                });
-                            }
-                        }
-                    }
-                },
-                condition if condition.starts_with("min_value:") => {
-                    if let Some(min_value_str) = condition.strip_prefix("min_value:") {
-                        if let Ok(min_value) = min_value_str.parse::<f64>() {
-                            if !value.is_number() || value.as_f64().map_or(true, |v| v < min_value) {
-                                errors.push(ValidationError {
-                                    path: path.clone(),
-                                    rule_id: rule.id.clone(),
-                                });
-                            }
-                        }
-                    }
-                },
-                condition if condition.starts_with("max_value:") => {
-                    if let Some(max_value_str) = condition.strip_prefix("max_value:") {
-                        if let Ok(max_value) = max_value_str.parse::<f64>() {
-                            if !value.is_number() || value.as_f64().map_or(true, |v| v > max_value) {
-                                errors.push(ValidationError {
-                                    path: path.clone(),
-                                    rule_id: rule.id.clone(),
-                                });
-                            }
-                        }
-                    }
-                },
-                condition if condition.starts_with("equals:") => {
-                    if let Some(expected_value) = condition.strip_prefix("equals:") {
-                        let matches = match value {
-                            Value::String(s) => s == expected_value,
-                            Value::Number(n) => {
-                                if let Ok(num) = expected_value.parse::<f64>() {
-                                    if let Some(val_num) = n.as_f64() {
-                                        (val_num - num).abs() < f64::EPSILON
-                                    } else {
-                                        false
-                                    }
-                                } else {
-                                    false
-                                }
-                            },
-                            Value::Bool(b) => {
-                                if let Ok(expected_bool) = expected_value.parse::<bool>() {
-                                    *b == expected_bool
-                                } else {
-                                    false
-                                }
-                            },
-                            _ => false,
-                        };
-                        
-                        if !matches {
-                            errors.push(ValidationError {
-                                path: path.clone(),
-                                rule_id: rule.id.clone(),
-                            });
-                        }
-                    }
-                },
-                condition if condition.starts_with("regex:") => {
-                    if let Some(pattern) = condition.strip_prefix("regex:") {
-                        if value.is_string() {
-                            match Regex::new(pattern) {
-                                Ok(regex) => {
-                                    if !regex.is_match(value.as_str().unwrap()) {
-                                        errors.push(ValidationError {
-                                            path: path.clone(),
-                                            rule_id: rule.id.clone(),
-                                        });
-                                    }
-                                },
-                                Err(_) => {
-                                    // Invalid regex pattern - consider this a configuration error
-                                    // For now, we'll just skip this validation
-                                }
-                            }
-                        } else {
-                            errors.push(ValidationError {
-                                path: path.clone(),
-                                rule_id: rule.id.clone(),
-                            });
-                        }
-                    }
-                },
-                _ => {
-                    // Unknown condition - consider this a configuration error
-                    // For now, we'll just skip this validation
-                }
+        for node in selection.iter() {
+            if let Some(failure) = compiled.condition.check(node.node(), &ctx) {
+                let (path, pointer) = scope.locate(compiled.selector.is_relative(), node.location());
+                errors.push(self.build_error(
+                    compiled,
+                    path,
+                    Some(pointer),
+                    Some(node.node()),
+                    failure.limit.as_deref(),
+                ));
             }
         }
         
         // If there are errors, we consider the condition to have failed
-        if !errors.is_empty() {
-            condition_passed = false;
+        (errors.is_empty(), errors)
+    }
+    
+    // Build a detailed error for a failed rule, rendering the rule's message template
+    fn build_error(
+        &self,
+        compiled: &CompiledRule,
+        path: String,
+        pointer: Option<String>,
+        value: Option<&Value>,
+        limit: Option<&str>,
+    ) -> ValidationError {
+        let rule = &compiled.rule;
+        let template = compiled.message_template
+            .as_deref()
+            .unwrap_or_else(|| default_message(&rule.condition));
+        
+        let message = render_message(template, &[
+            ("path", &path),
+            ("value", &display_value(value)),
+            ("limit", limit.unwrap_or("")),
+            ("rule_id", &rule.id),
+            ("condition", &rule.condition),
+        ]);
+        
+        ValidationError {
+            path,
+            rule_id: rule.id.clone(),
+            pointer,
+            condition: rule.condition.clone(),
+            actual_value: value.cloned(),
+            severity: compiled.severity,
+            message,
+        }
+    }
+    
+    // Apply a rule and, for "if", "else_if" and "switch" rules, the branches it selects.
+    // A scoped rule is applied per element, and its branches are evaluated in that element's scope.
+    fn apply_with_branches(
+        &self,
+        repository: &RuleRepository,
+        json: &Value,
+        compiled: &CompiledRule,
+        current: &Scope,
+    ) -> Vec<ValidationError> {
+        let rule = &compiled.rule;
+        let mut errors = Vec::new();
+        
+        for scope in self.scopes_for(json, compiled, current) {
+            let (result, rule_errors) = self.apply_in_scope(json, compiled, &scope);
+            if compiled.reports_errors() {
+                errors.extend(rule_errors);
+            }
+            
+            if rule.is_condition_root() {
+                // A predicate over nothing is false: an element without the field takes the
+                // else branch instead of passing vacuously into the then branch
+                let holds = result && (compiled.condition.is_aggregate() || self.selects_any(json, compiled, &scope));
+                errors.extend(self.process_conditional_rules(repository, json, &rule.id, holds, &scope));
+            } else if rule.is_switch() {
+                errors.extend(self.process_switch_rules(repository, json, compiled, &scope));
+            }
         }
         
-        Ok((condition_passed, errors))
+        errors
+    }
+    
+    // Whether the rule's selector matches anything within a scope
+    fn selects_any(&self, json: &Value, compiled: &CompiledRule, scope: &Scope) -> bool {
+        let ctx = CheckContext { root: json, scope: scope.value, now: self.clock.now() };
+        !compiled.selector.query(&ctx).is_empty()
     }
     
     // Process conditional branches based on the result of the "if" rule
-    fn process_conditional_rules(&self, json: &Value, parent_id: &str, condition_passed: bool) -> Vec<ValidationError> {
+    fn process_conditional_rules(
+        &self,
+        repository: &RuleRepository,
+        json: &Value,
+        parent_id: &str,
+        condition_passed: bool,
+        scope: &Scope,
+    ) -> Vec<ValidationError> {
         // Get the appropriate branch based on the condition result
-        let (then_rules, else_rules) = self.rule_repository.get_conditional_rules(parent_id);
+        let (then_rules, else_rules) = repository.get_conditional_rules(parent_id);
         let branch_to_process = if condition_passed { then_rules } else { else_rules };
         
         let mut all_errors = Vec::new();
         
-        // Process all rules in the selected branch
-        for rule in branch_to_process {
-            // If this is another conditional root, process it recursively
-            if rule.is_condition_root() {
-                match self.apply_rule(json, &rule) {
-                    Ok((result, errors)) => {
-                        all_errors.extend(errors);
-                        // Process nested conditionals
-                        let nested_errors = self.process_conditional_rules(json, &rule.id, result);
-                        all_errors.extend(nested_errors);
-                    },
-                    Err(e) => {
-                        log::error!("Error processing conditional rule {}: {}", rule.id, e);
-                    }
-                }
-            } else {
-                // Process standard rule
-                match self.apply_rule(json, &rule) {
-                    Ok((_, errors)) => {
-                        all_errors.extend(errors);
-                    },
-                    Err(e) => {
-                        log::error!("Error processing rule {}: {}", rule.id, e);
-                    }
-                }
+        // Process all rules in the selected branch; nested "else_if" and "switch"
+        // rules process their own branches in turn
+        for compiled in branch_to_process {
+            all_errors.extend(self.apply_with_branches(repository, json, &compiled, scope));
+        }
+        
+        all_errors
+    }
+    
+    // Process the case branches matching the value selected by a "switch" rule,
+    // or its default branch when no case matches
+    fn process_switch_rules(
+        &self,
+        repository: &RuleRepository,
+        json: &Value,
+        switch: &CompiledRule,
+        scope: &Scope,
+    ) -> Vec<ValidationError> {
+        let ctx = CheckContext { root: json, scope: scope.value, now: self.clock.now() };
+        
+        // A switch whose dependency isn't met selects no branch at all
+        if let Some(dependency) = &switch.dependency {
+            if !dependency.is_met(&ctx) {
+                return Vec::new();
             }
         }
         
+        let selection = switch.selector.query(&ctx);
+        let branch_to_process = repository
+            .get_switch_rules(&switch.rule.id)
+            .select(selection.first());
+        
+        let mut all_errors = Vec::new();
+        
+        for compiled in branch_to_process {
+            all_errors.extend(self.apply_with_branches(repository, json, &compiled, scope));
+        }
+        
         all_errors
     }
-}
\ No newline at end of file
+}
+
+// Where rules are evaluated: the whole document, or an element selected by a rule's scope
+#[derive(Clone)]
+struct Scope<'a> {
+    value: &'a Value,
+    // Location of the element in dot notation ("$" for the document) and as a JSON Pointer
+    path: String,
+    pointer: String,
+}
+
+impl<'a> Scope<'a> {
+    fn root(json: &'a Value) -> Self {
+        Scope { value: json, path: String::from("$"), pointer: String::new() }
+    }
+    
+    // Path and pointer of a node found by a selector; relative selectors' locations
+    // are relative to this scope
+    fn locate(&self, relative: bool, location: &NormalizedPath) -> (String, String) {
+        let path = format_path(location);
+        let pointer = location.to_json_pointer();
+        if relative {
+            (format!("{}{}", self.path, &path[1..]), format!("{}{}", self.pointer, pointer))
+        } else {
+            (path, pointer)
+        }
+    }
+    
+    // Path and pointer of a rule's selector itself, for failures that aren't about a single node
+    fn locate_selector(&self, compiled: &CompiledRule) -> (String, Option<String>) {
+        let selector = compiled.rule.selector.trim();
+        if !compiled.selector.is_relative() {
+            return (selector.to_string(), selector_to_pointer(selector));
+        }
+        
+        let relative = &selector[1..];
+        let path = format!("{}{}", self.path, relative);
+        let pointer = selector_to_pointer(&format!("${}", relative)).map(|pointer| format!("{}{}", self.pointer, pointer));
+        (path, pointer)
+    }
+}
+
+// Render a resolved location in dot notation, e.g. "$.application.individuals.data[2].names[0].name.first".
+// Member names that aren't plain identifiers fall back to bracket notation.
+fn format_path(location: &NormalizedPath) -> String {
+    let mut path = String::from("$");
+    
+    for element in location.iter() {
+        match element {
+            PathElement::Name(name) => {
+                let is_identifier = !name.is_empty()
+                    && !name.starts_with(|c: char| c.is_ascii_digit())
+                    && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+                if is_identifier {
+                    path.push('.');
+                    path.push_str(name);
+                } else {
+                    path.push_str(&format!("['{}']", name.replace('\\', "\\\\").replace('\'', "\\'")));
+                }
+            },
+            PathElement::Index(index) => path.push_str(&format!("[{}]", index)),
+        }
+    }
+    
+    path
+}
+
+// Convert a simple selector such as "$.user.name" or "$.items[0].id" into a JSON Pointer.
+// Selectors with wildcards, filters or recursion don't name a single location, so they yield None.
+fn selector_to_pointer(selector: &str) -> Option<String> {
+    let rest = selector.strip_prefix('$')?;
+    let mut pointer = String::new();
+    
+    for segment in rest.split('.').filter(|s| !s.is_empty()) {
+        let (name, indices) = match segment.find('[') {
+            Some(pos) => segment.split_at(pos),
+            None => (segment, ""),
+        };
+        if name.is_empty() && indices.is_empty() || name == "*" {
+            return None;
+        }
+        if !name.is_empty() {
+            pointer.push('/');
+            pointer.push_str(&name.replace('~', "~0").replace('/', "~1"));
+        }
+        for index in indices.split('[').filter(|s| !s.is_empty()) {
+            let index = index.strip_suffix(']')?;
+            index.parse::<usize>().ok()?;
+            pointer.push('/');
+            pointer.push_str(index);
+        }
+    }
+    
+    Some(pointer)
+}
+
+// Default message templates used when a rule has no "message" parameter
+fn default_message(condition: &str) -> &'static str {
+    // A guard ("... when $.x == 1") doesn't change how a failure is described
+    let condition = split_guard(condition).map_or(condition, |(inner, _)| inner).trim();
+    match condition.split([':', '(']).next().unwrap_or(condition) {
+        "required" => "{path} is required",
+        "is_number" => "{path} must be a number but was {value}",
+        "is_string" => "{path} must be a string but was {value}",
+        "is_boolean" => "{path} must be a boolean but was {value}",
+        "is_array" => "{path} must be an array but was {value}",
+        "is_object" => "{path} must be an object but was {value}",
+        "is_null" => "{path} must be null but was {value}",
+        "min_length" => "{path} must be at least {limit} characters long",
+        "max_length" => "{path} must be at most {limit} characters long",
+        "min_value" => "{path} must be greater than or equal to {limit} but was {value}",
+        "max_value" => "{path} must be less than or equal to {limit} but was {value}",
+        "equals" => "{path} must equal {limit} but was {value}",
+        "regex" => "{path} must match the pattern {limit}",
+        "equals_field" => "{path} must equal {limit} but was {value}",
+        "not_equals_field" => "{path} must not equal {limit}",
+        "less_than_field" => "{path} must be less than {limit} but was {value}",
+        "less_or_equal_field" => "{path} must be less than or equal to {limit} but was {value}",
+        "greater_than_field" => "{path} must be greater than {limit} but was {value}",
+        "greater_or_equal_field" => "{path} must be greater than or equal to {limit} but was {value}",
+        "sum_equals_field" => "sum of {path} must equal {limit} but was {value}",
+        "is_date" => "{path} must be a date in the format {limit} but was {value}",
+        "is_datetime" => "{path} must be an RFC 3339 timestamp but was {value}",
+        "date_before" => "{path} must be before {limit} but was {value}",
+        "date_after" => "{path} must be after {limit} but was {value}",
+        "in" | "in_ci" => "{path} must be one of {limit} but was {value}",
+        "not_in" | "not_in_ci" => "{path} must not be one of {limit} but was {value}",
+        "min_items" => "{path} must have at least {limit} items",
+        "max_items" => "{path} must have at most {limit} items",
+        "unique_items" => "{path} must not contain duplicate items (found {limit} more than once)",
+        "unique_by" => "{path} must not contain items with the same key (found {limit} more than once)",
+        "contains" => "{path} must contain {limit}",
+        "expr" => "{path} must satisfy {limit}",
+        "all_of" => "{path} must satisfy all of {limit} but was {value}",
+        "any_of" => "{path} must satisfy at least one of {limit} but was {value}",
+        "one_of" => "{path} must satisfy exactly one of {limit} but was {value}",
+        "not" => "{path} must not satisfy {limit} but was {value}",
+        "min_age" => "{path} must imply an age of at least {limit} years but was {value}",
+        "max_age" => "{path} must imply an age of at most {limit} years but was {value}",
+        _ => "{path} failed condition {condition}",
+    }
+}
+
+// Substitute {placeholder} occurrences in a message template in a single pass, so
+// substituted values (which may come from the payload) are never expanded again.
+// Unknown placeholders are left as they are.
+fn render_message(template: &str, placeholders: &[(&str, &str)]) -> String {
+    let mut message = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        message.push_str(&rest[..start]);
+        let tail = &rest[start..];
+        let value = tail.find('}').and_then(|end| {
+            let name = &tail[1..end];
+            placeholders.iter().find(|(n, _)| *n == name).map(|(_, value)| (*value, end))
+        });
+        match value {
+            Some((value, end)) => {
+                message.push_str(value);
+                rest = &tail[end + 1..];
+            },
+            None => {
+                message.push('{');
+                rest = &tail[1..];
+            },
+        }
+    }
+    message.push_str(rest);
+    message
+}
+
+// Strings are shown without quotes, everything else as compact JSON
+fn display_value(value: Option<&Value>) -> String {
+    match value {
+        Some(Value::String(s)) => s.clone(),
+        Some(other) => other.to_string(),
+        None => "missing".to_string(),
+    }
+}
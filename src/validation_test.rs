@@ -1,6 +1,6 @@
 use serde_json::json;
 use crate::validation::ValidationEngine;
-use crate::models::ValidationRule;
+use crate::models::{Severity, ValidationRule};
 use crate::rules::RuleRepository;
 
 // Helper function to create a simple rule for testing
@@ -20,6 +20,8 @@ fn create_test_rule(id: &str, selector: &str, condition: &str, key_fields: &str)
         parameters: HashMap::new(),
         logic_type: ConditionalLogic::Standard,
         parent_rule_id: "".to_string(),
+        case_value: "".to_string(),
+        scope: "".to_string(),
     }
 }
 
@@ -226,10 +228,10 @@ fn test_complete_validation_flow() {
     );
     
     // Add rules to repository
-    repo.add_rule("user.name".to_string(), name_rule);
-    repo.add_rule("user.age".to_string(), age_rule);
-    repo.add_rule("user.age".to_string(), age_min_rule);
-    repo.add_rule("user.email".to_string(), email_rule);
+    repo.add_rule("user.name".to_string(), name_rule).unwrap();
+    repo.add_rule("user.age".to_string(), age_rule).unwrap();
+    repo.add_rule("user.age".to_string(), age_min_rule).unwrap();
+    repo.add_rule("user.email".to_string(), email_rule).unwrap();
     
     // Create validation engine
     let engine = ValidationEngine::new(repo);
@@ -303,19 +305,12 @@ fn test_conditional_validation() {
     bank_account_rule.logic_type = ConditionalLogic::Else;
     bank_account_rule.parent_rule_id = "payment_type".to_string();
     
-    // Add rules to repository 
-    repo.add_rule("payment.type".to_string(), payment_type_rule.clone());
-    
-    // Manually set up conditional rules
-    let mut then_rules = Vec::new();
-    then_rules.push(cc_number_rule.clone());
-    then_rules.push(cc_cvv_rule.clone());
-    
-    let mut else_rules = Vec::new();
-    else_rules.push(bank_routing_rule.clone());
-    else_rules.push(bank_account_rule.clone());
-    
-    repo.conditional_rules.insert("payment_type".to_string(), (then_rules, else_rules));
+    // Add rules to repository - then/else rules are attached to their parent
+    repo.add_rule("payment.type".to_string(), payment_type_rule.clone()).unwrap();
+    repo.add_rule("payment.credit_card.number".to_string(), cc_number_rule).unwrap();
+    repo.add_rule("payment.credit_card.cvv".to_string(), cc_cvv_rule).unwrap();
+    repo.add_rule("payment.bank.routing".to_string(), bank_routing_rule).unwrap();
+    repo.add_rule("payment.bank.account".to_string(), bank_account_rule).unwrap();
     
     // Create validation engine
     let engine = ValidationEngine::new(repo);
@@ -352,19 +347,8 @@ fn test_conditional_validation() {
     assert_eq!(result.errors.len(), 1);
     assert_eq!(result.errors[0].rule_id, "cc_cvv");
     
-    // For a bank payment, we need to only test the else branch conditions
-    // This is a simplified test that just checks if we can validate bank fields
-    let mut bank_repo = RuleRepository::new();
-    
-    // Add only the bank account rules - make sure they're standard rules, not conditional branches
-    let bank_routing_rule = create_test_rule("bank_routing", "$.payment.bank.routing", "required", "payment.bank.routing");
-    let bank_account_rule = create_test_rule("bank_account", "$.payment.bank.account", "required", "payment.bank.account");
-    
-    bank_repo.add_rule("payment.bank.routing".to_string(), bank_routing_rule);
-    bank_repo.add_rule("payment.bank.account".to_string(), bank_account_rule);
-    
-    let bank_engine = ValidationEngine::new(bank_repo);
-    
+    // A bank payment fails the "if" rule and takes the else branch; the failed
+    // predicate itself is not an error
     let bank_payment = json!({
         "payment": {
             "type": "bank_transfer", 
@@ -375,8 +359,8 @@ fn test_conditional_validation() {
         }
     });
     
-    let result = bank_engine.validate(&bank_payment, "DEFAULT", "ALL").unwrap();
-    assert!(result.valid);
+    let result = engine.validate(&bank_payment, "DEFAULT", "ALL").unwrap();
+    assert!(result.valid, "unexpected errors: {:?}", result.errors);
     
     // Test bank payment with missing fields (should fail)
     let invalid_bank_payment = json!({
@@ -389,9 +373,936 @@ fn test_conditional_validation() {
         }
     });
     
-    let result = bank_engine.validate(&invalid_bank_payment, "DEFAULT", "ALL").unwrap();
-    println!("Invalid bank payment validation result: {:?}", result);
+    let result = engine.validate(&invalid_bank_payment, "DEFAULT", "ALL").unwrap();
     assert!(!result.valid);
     assert_eq!(result.errors.len(), 1);
     assert_eq!(result.errors[0].rule_id, "bank_account");
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_error_details() {
+    let rule = create_test_rule("test_min_length", "$.users[*].name", "min_length:3", "users.name");
+    
+    let json = json!({"users": [{"name": "John"}, {"name": "Jo"}]});
+    let (result, errors) = ValidationEngine::new(RuleRepository::new())
+        .apply_rule(&json, &rule)
+        .unwrap();
+    assert!(!result);
+    assert_eq!(errors.len(), 1);
+    
+    let error = &errors[0];
+    assert_eq!(error.path, "$.users[1].name");
+    assert_eq!(error.pointer.as_deref(), Some("/users/1/name"));
+    assert_eq!(error.condition, "min_length:3");
+    assert_eq!(error.actual_value, Some(json!("Jo")));
+    assert_eq!(error.severity, Severity::Error);
+    assert!(error.message.contains("at least 3 characters"));
+    
+    // Missing fields have no observed value but still get a pointer for simple selectors
+    let rule = create_test_rule("test_required", "$.user.name", "required", "user.name");
+    let (_, errors) = ValidationEngine::new(RuleRepository::new())
+        .apply_rule(&json!({"user": {}}), &rule)
+        .unwrap();
+    assert_eq!(errors[0].pointer.as_deref(), Some("/user/name"));
+    assert_eq!(errors[0].actual_value, None);
+}
+
+#[test]
+fn test_error_message_template_and_severity() {
+    let mut rule = create_test_rule("test_max_value", "$.age", "max_value:65", "age");
+    rule.parameters.insert("message".to_string(), json!("Age {value} exceeds {limit}"));
+    rule.parameters.insert("severity".to_string(), json!("warning"));
+    
+    let mut repo = RuleRepository::new();
+    repo.add_rule("age".to_string(), rule).unwrap();
+    let engine = ValidationEngine::new(repo);
+    
+    let result = engine.validate(&json!({"age": 70}), "DEFAULT", "ALL").unwrap();
+    assert_eq!(result.errors.len(), 1);
+    assert_eq!(result.errors[0].message, "Age 70 exceeds 65");
+    assert_eq!(result.errors[0].severity, Severity::Warning);
+    
+    // Warnings are reported but don't make the payload invalid
+    assert!(result.valid);
+    
+    // Placeholder text in the payload is shown as it is, not expanded
+    let mut rule = create_test_rule("test_max_length", "$.code", "max_length:3", "code");
+    rule.parameters.insert("message".to_string(), json!("got {value}, need {limit} {unknown}"));
+    let mut repo = RuleRepository::new();
+    repo.add_rule("code".to_string(), rule).unwrap();
+    let engine = ValidationEngine::new(repo);
+    
+    let result = engine.validate(&json!({"code": "{limit}"}), "DEFAULT", "ALL").unwrap();
+    assert_eq!(result.errors[0].message, "got {limit}, need 3 {unknown}");
+}
+
+#[test]
+fn test_resolved_paths_for_nested_arrays() {
+    let rule = create_test_rule(
+        "first_name_length",
+        "$.application.individuals.data[*].names[*].name.first",
+        "min_length:2",
+        "application.individuals.names.name"
+    );
+    
+    let json = json!({
+        "application": {
+            "individuals": {
+                "data": [
+                    {"names": [{"name": {"first": "Alice"}}]},
+                    {"names": [{"name": {"first": "Bob"}}, {"name": {"first": "B"}}]},
+                    {"names": [{"name": {"first": "C"}}]}
+                ]
+            }
+        }
+    });
+    
+    let (_, errors) = ValidationEngine::new(RuleRepository::new())
+        .apply_rule(&json, &rule)
+        .unwrap();
+    let paths: Vec<&str> = errors.iter().map(|e| e.path.as_str()).collect();
+    assert_eq!(paths, vec![
+        "$.application.individuals.data[1].names[1].name.first",
+        "$.application.individuals.data[2].names[0].name.first",
+    ]);
+    
+    // Member names that aren't identifiers use bracket notation
+    let rule = create_test_rule("odd_key", "$['first name']", "min_length:2", "first name");
+    let (_, errors) = ValidationEngine::new(RuleRepository::new())
+        .apply_rule(&json!({"first name": "A"}), &rule)
+        .unwrap();
+    assert_eq!(errors[0].path, "$['first name']");
+}
+
+#[test]
+fn test_invalid_rules_rejected_at_compile_time() {
+    let engine = ValidationEngine::new(RuleRepository::new());
+    let json = json!({"name": "John"});
+    
+    // Unknown conditions, bad limits and bad regexes no longer silently pass
+    for condition in ["min_lenght:3", "min_length:abc", "regex:[unclosed"] {
+        let rule = create_test_rule("bad_rule", "$.name", condition, "name");
+        assert!(engine.apply_rule(&json, &rule).is_err(), "{} should be rejected", condition);
+    }
+    
+    let rule = create_test_rule("bad_selector", "$.name[", "required", "name");
+    assert!(engine.apply_rule(&json, &rule).is_err());
+    
+    let mut repo = RuleRepository::new();
+    let rule = create_test_rule("bad_rule", "$.name", "min_lenght:3", "name");
+    assert!(repo.add_rule("name".to_string(), rule).is_err());
+}
+
+#[test]
+fn test_rule_edits_publish_new_snapshot() {
+    use std::sync::Arc;
+    use crate::models::NewRuleRequest;
+    
+    let mut repo = RuleRepository::new();
+    repo.add_rule("name".to_string(), create_test_rule("name_required", "$.name", "required", "name")).unwrap();
+    let engine = Arc::new(ValidationEngine::new(repo));
+    
+    // A validation in flight keeps using the snapshot it started with
+    let snapshot = engine.rules();
+    
+    let request = NewRuleRequest {
+        field_path: "$.name".to_string(),
+        validation_type: "min_length:3".to_string(),
+        parameters: None,
+        description: None,
+        journey: "DEFAULT".to_string(),
+        system: "ALL".to_string(),
+        ..Default::default()
+    };
+    
+    // Readers and a writer run concurrently without blocking each other
+    let readers: Vec<_> = (0..4)
+        .map(|_| {
+            let engine = Arc::clone(&engine);
+            std::thread::spawn(move || {
+                for _ in 0..100 {
+                    engine.validate(&json!({"name": "Jo"}), "DEFAULT", "ALL").unwrap();
+                }
+            })
+        })
+        .collect();
+    let new_rule_id = engine.create_rule(&request).unwrap();
+    for reader in readers {
+        reader.join().unwrap();
+    }
+    
+    assert!(snapshot.get_rules_for_key_field("$.name").is_empty());
+    assert_eq!(engine.rules().get_rules_for_key_field("$.name").len(), 1);
+    
+    let result = engine.validate(&json!({"name": "Jo"}), "DEFAULT", "ALL").unwrap();
+    assert_eq!(result.errors.len(), 1);
+    assert_eq!(result.errors[0].rule_id, new_rule_id);
+    
+    // A failing edit leaves the published rules untouched
+    let bad_request = NewRuleRequest { validation_type: "min_lenght:3".to_string(), ..request };
+    assert!(engine.create_rule(&bad_request).is_err());
+    assert_eq!(engine.rules().rule_count(), 2);
+}
+
+#[test]
+fn test_validation_cache_on_hot_path() {
+    use crate::cache::CacheConfig;
+    
+    let mut repo = RuleRepository::new();
+    repo.add_rule("name".to_string(), create_test_rule("name_required", "$.name", "required", "name")).unwrap();
+    let engine = ValidationEngine::with_cache_config(repo, CacheConfig { capacity: 2, ttl: None });
+    
+    let payload = json!({"name": "John"});
+    engine.validate(&payload, "DEFAULT", "ALL").unwrap();
+    engine.validate(&payload, "DEFAULT", "ALL").unwrap();
+    
+    let stats = engine.validation_cache_stats();
+    assert_eq!((stats.size, stats.hits, stats.misses), (1, 1, 1));
+    
+    // The rule selection for full evaluations was computed once and reused
+    let options = crate::models::ValidationOptions { full_evaluation: true };
+    engine.validate_with_options(&payload, "DEFAULT", "ALL", &options).unwrap();
+    engine.validate_with_options(&json!({"name": "Jane"}), "DEFAULT", "ALL", &options).unwrap();
+    let stats = engine.journey_system_cache_stats();
+    assert_eq!((stats.size, stats.hits, stats.misses), (1, 1, 1));
+    
+    // Capacity bounds the number of cached results
+    for i in 0..10 {
+        engine.validate(&json!({"name": format!("user{}", i)}), "DEFAULT", "ALL").unwrap();
+    }
+    assert!(engine.get_validation_cache_size() <= 2);
+}
+
+#[test]
+fn test_cache_invalidated_on_rule_change() {
+    let mut repo = RuleRepository::new();
+    repo.add_rule("name".to_string(), create_test_rule("name_required", "$.name", "required", "name")).unwrap();
+    let engine = ValidationEngine::new(repo);
+    
+    let payload = json!({"name": "Jo"});
+    assert!(engine.validate(&payload, "DEFAULT", "ALL").unwrap().valid);
+    
+    // Deleting the only rule and adding a stricter one must not serve the cached result
+    engine.delete_rule("name_required").unwrap();
+    assert_eq!(engine.get_validation_cache_size(), 0);
+    assert_eq!(engine.get_journey_system_cache_size(), 0);
+    
+    let request = crate::models::NewRuleRequest {
+        field_path: "$.name".to_string(),
+        validation_type: "min_length:3".to_string(),
+        parameters: None,
+        description: None,
+        journey: "DEFAULT".to_string(),
+        system: "ALL".to_string(),
+        ..Default::default()
+    };
+    engine.create_rule(&request).unwrap();
+    assert!(!engine.validate(&payload, "DEFAULT", "ALL").unwrap().valid);
+}
+
+#[test]
+fn test_rules_selected_by_payload_key_fields() {
+    use crate::models::ValidationOptions;
+    
+    let mut repo = RuleRepository::new();
+    repo.add_rule("application.individuals.names.name".to_string(), create_test_rule(
+        "name_required",
+        "$.application.individuals.data[*].names[*].name",
+        "required",
+        "application.individuals.names.name",
+    )).unwrap();
+    repo.add_rule("payment.card.number".to_string(), create_test_rule(
+        "card_number_required", "$.payment.card.number", "required", "payment.card.number",
+    )).unwrap();
+    repo.add_rule("$.user.email".to_string(), create_test_rule(
+        "email_required", "$.user.email", "required", "$.user.email",
+    )).unwrap();
+    let engine = ValidationEngine::new(repo);
+    
+    let payload = json!({
+        "application": {
+            "individuals": {"data": [{"names": [{"name": {"first": "Ann"}}]}]}
+        }
+    });
+    
+    // Only the rule whose key field is present is evaluated, found through the key field
+    // index even though the payload has a "data" container the key field skips; the
+    // payment and user rules would fail but are not triggered by this payload
+    let options = ValidationOptions::default();
+    let selected = engine.get_rules_for_payload(&payload, "DEFAULT", "ALL", &options);
+    assert_eq!(selected.len(), 1);
+    assert_eq!(selected[0].rule.id, "name_required");
+    assert!(engine.validate(&payload, "DEFAULT", "ALL").unwrap().valid);
+    
+    // A rule still fires for a missing field when its parent object is present
+    let result = engine.validate(&json!({"payment": {"card": {}}}), "DEFAULT", "ALL").unwrap();
+    assert_eq!(result.errors.len(), 1);
+    assert_eq!(result.errors[0].rule_id, "card_number_required");
+    
+    // So does a rule whose whole subtree is missing, as long as its top-level key is present
+    let result = engine.validate(&json!({"payment": {"cash": 10}}), "DEFAULT", "ALL").unwrap();
+    let failed: Vec<_> = result.errors.iter().map(|e| e.rule_id.as_str()).collect();
+    assert_eq!(failed, vec!["card_number_required"]);
+    let selected = engine.get_rules_for_payload(&json!({"application": {}}), "DEFAULT", "ALL", &options);
+    assert_eq!(selected.len(), 1);
+    assert_eq!(selected[0].rule.id, "name_required");
+    
+    // Otherwise keys must appear along one path of the payload, in order
+    let payload_elsewhere = json!({"card": {"payment": {}}, "other": {"payment": [1, 2]}});
+    assert!(engine.get_rules_for_payload(&payload_elsewhere, "DEFAULT", "ALL", &options).is_empty());
+    
+    // Forcing full evaluation runs every rule for the journey
+    let options = ValidationOptions { full_evaluation: true };
+    let result = engine.validate_with_options(&payload, "DEFAULT", "ALL", &options).unwrap();
+    let mut failed: Vec<_> = result.errors.iter().map(|e| e.rule_id.as_str()).collect();
+    failed.sort();
+    assert_eq!(failed, vec!["card_number_required", "email_required"]);
+}
+
+#[test]
+fn test_cross_field_comparisons() {
+    let engine = ValidationEngine::new(RuleRepository::new());
+    let apply = |condition: &str, selector: &str, json: &serde_json::Value| {
+        engine.apply_rule(json, &create_test_rule("cross_field", selector, condition, "")).unwrap()
+    };
+    
+    let json = json!({
+        "password": "s3cret",
+        "password_confirm": "s3cret",
+        "start_date": "2024-01-01",
+        "end_date": "2024-03-31",
+        "loan": {"amount": 5000, "min": 1000}
+    });
+    
+    assert!(apply("equals_field:$.password_confirm", "$.password", &json).0);
+    assert!(apply("less_than_field:$.end_date", "$.start_date", &json).0);
+    assert!(apply("greater_or_equal_field:$.loan.min", "$.loan.amount", &json).0);
+    assert!(!apply("greater_than_field:$.loan.amount", "$.loan.min", &json).0);
+    assert!(apply("not_equals_field:$.end_date", "$.start_date", &json).0);
+    
+    // A missing referenced field fails the comparison
+    let (result, errors) = apply("equals_field:$.missing", "$.password", &json);
+    assert!(!result);
+    assert_eq!(errors[0].message, "$.password must equal $.missing (missing) but was s3cret");
+    
+    let json = json!({"start_date": "2024-05-01", "end_date": "2024-03-31"});
+    let (result, errors) = apply("less_than_field:$.end_date", "$.start_date", &json);
+    assert!(!result);
+    assert_eq!(errors[0].message, "$.start_date must be less than $.end_date (2024-03-31) but was 2024-05-01");
+}
+
+#[test]
+fn test_sum_equals_field() {
+    let engine = ValidationEngine::new(RuleRepository::new());
+    let rule = create_test_rule("items_total", "$.items[*].amount", "sum_equals_field:$.total", "");
+    
+    let json = json!({"items": [{"amount": 10.1}, {"amount": 20.2}], "total": 30.3});
+    let (result, errors) = engine.apply_rule(&json, &rule).unwrap();
+    assert!(result, "unexpected errors: {:?}", errors);
+    
+    // One error for the whole selection, reporting the computed sum
+    let json = json!({"items": [{"amount": 10}, {"amount": 25}], "total": 30});
+    let (result, errors) = engine.apply_rule(&json, &rule).unwrap();
+    assert!(!result);
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].path, "$.items[*].amount");
+    assert_eq!(errors[0].actual_value, Some(json!(35)));
+    assert_eq!(errors[0].message, "sum of $.items[*].amount must equal $.total (30) but was 35");
+    
+    // Invalid referenced selectors are rejected when the rule is compiled
+    let rule = create_test_rule("bad_ref", "$.items[*].amount", "sum_equals_field:total", "");
+    assert!(engine.apply_rule(&json, &rule).is_err());
+}
+
+#[test]
+fn test_date_conditions() {
+    use std::sync::Arc;
+    use chrono::{TimeZone, Utc};
+    use crate::dates::FixedClock;
+    
+    let now = Utc.with_ymd_and_hms(2024, 6, 15, 12, 0, 0).unwrap();
+    let engine = ValidationEngine::new(RuleRepository::new()).with_clock(Arc::new(FixedClock(now)));
+    let check = |condition: &str, value: serde_json::Value| {
+        let rule = create_test_rule("date_rule", "$.value", condition, "");
+        engine.apply_rule(&json!({"value": value}), &rule).unwrap()
+    };
+    
+    assert!(check("is_date", json!("2024-02-29")).0);
+    assert!(!check("is_date", json!("2023-02-29")).0);
+    assert!(check("is_date:%d/%m/%Y", json!("31/12/2023")).0);
+    assert!(!check("is_date:%d/%m/%Y", json!("2023-12-31")).0);
+    assert!(check("is_datetime", json!("2024-06-15T08:30:00+02:00")).0);
+    assert!(!check("is_datetime", json!("2024-06-15 08:30")).0);
+    
+    // Absolute bounds, with timestamps compared as instants and dates by day
+    assert!(check("date_before:2024-01-01", json!("2023-12-31")).0);
+    assert!(!check("date_before:2024-01-01", json!("2024-01-01")).0);
+    assert!(check("date_after:2024-01-01T00:00:00Z", json!("2024-01-01T00:00:01Z")).0);
+    
+    // Relative bounds resolve against the injected clock
+    assert!(check("date_after:now", json!("2024-06-16")).0);
+    assert!(!check("date_after:now+30d", json!("2024-07-01")).0);
+    let (result, errors) = check("date_before:now-18y", json!("2010-01-01"));
+    assert!(!result);
+    assert_eq!(errors[0].message, "$.value must be before now-18y (2006-06-15) but was 2010-01-01");
+    
+    // Age checks from a date of birth, turning 18 on the clock's date
+    assert!(check("min_age:18", json!("2006-06-15")).0);
+    assert!(!check("min_age:18", json!("2006-06-16")).0);
+    assert!(check("max_age:65", json!("1958-06-16")).0);
+    assert!(!check("max_age:65", json!("1958-06-15")).0);
+    assert!(!check("min_age:18", json!("not a date")).0);
+    
+    // Malformed formats and bounds are rejected at compile time
+    for condition in ["is_date:%Q", "date_before:yesterday", "date_after:now-18x", "date_before:now18y"] {
+        let rule = create_test_rule("bad_date", "$.value", condition, "");
+        assert!(engine.apply_rule(&json!({}), &rule).is_err(), "{} should be rejected", condition);
+    }
+}
+
+#[test]
+fn test_allowed_values_conditions() {
+    let engine = ValidationEngine::new(RuleRepository::new());
+    let check = |condition: &str, value: serde_json::Value| {
+        let rule = create_test_rule("allowed_values", "$.value", condition, "");
+        engine.apply_rule(&json!({"value": value}), &rule).unwrap()
+    };
+    
+    assert!(check("in:[USD,EUR,GBP]", json!("EUR")).0);
+    assert!(!check("in:[USD,EUR,GBP]", json!("eur")).0);
+    assert!(check("in_ci:[USD,EUR,GBP]", json!("eur")).0);
+    assert!(check("in:[1, 2, 3]", json!(2)).0);
+    
+    // Numbers match by value, whichever way the list or the payload writes them
+    assert!(check("in:[1.0, 2]", json!(1)).0);
+    assert!(check("in:[1.0, 2]", json!(1.0)).0);
+    assert!(check("in:[1.0, 2]", json!(2.0)).0);
+    assert!(!check("in:[1.0, 2]", json!(1.5)).0);
+    assert!(!check("not_in:[1.0, 2]", json!(2.0)).0);
+    assert!(check("not_in:[XXX,ZZZ]", json!("USD")).0);
+    assert!(!check("not_in_ci:[XXX,ZZZ]", json!("xxx")).0);
+    
+    let (result, errors) = check("in:[USD,EUR]", json!("JPY"));
+    assert!(!result);
+    assert_eq!(errors[0].message, "$.value must be one of [USD, EUR] but was JPY");
+    
+    // Reference lists must exist when the rule is compiled
+    let rule = create_test_rule("allowed_values", "$.value", "in:@currencies", "");
+    assert!(engine.apply_rule(&json!({}), &rule).is_err());
+    let rule = create_test_rule("allowed_values", "$.value", "in:USD,EUR", "");
+    assert!(engine.apply_rule(&json!({}), &rule).is_err());
+}
+
+#[test]
+fn test_array_conditions() {
+    let engine = ValidationEngine::new(RuleRepository::new());
+    let json = json!({
+        "application": {
+            "individuals": [
+                {"id": "A1", "roles": ["primary", "borrower"]},
+                {"id": "A2", "roles": ["borrower"]},
+                {"id": "A1", "roles": ["guarantor", "guarantor"]}
+            ]
+        }
+    });
+    let check = |condition: &str, selector: &str| {
+        engine.apply_rule(&json, &create_test_rule("array_rule", selector, condition, "")).unwrap()
+    };
+    
+    assert!(check("min_items:1", "$.application.individuals").0);
+    assert!(check("max_items:4", "$.application.individuals").0);
+    assert!(!check("max_items:2", "$.application.individuals").0);
+    assert!(check("contains:primary", "$.application.individuals[0].roles").0);
+    assert!(!check("contains:primary", "$.application.individuals[1].roles").0);
+    assert!(!check("min_items:1", "$.application").0);
+    
+    // Errors are reported against the array itself
+    let (result, errors) = check("unique_by:$.id", "$.application.individuals");
+    assert!(!result);
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].path, "$.application.individuals");
+    assert_eq!(errors[0].message, "$.application.individuals must not contain items with the same key (found $.id = \"A1\" more than once)");
+    
+    // Each selected array is checked on its own
+    let (result, errors) = check("unique_items", "$.application.individuals[*].roles");
+    assert!(!result);
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].path, "$.application.individuals[2].roles");
+    
+    let (result, errors) = check("min_items:2", "$.application.individuals[*].roles");
+    assert!(!result);
+    assert_eq!(errors[0].message, "$.application.individuals[1].roles must have at least 2 items");
+}
+
+#[test]
+fn test_guarded_conditions() {
+    let engine = ValidationEngine::new(RuleRepository::new());
+    let rule = create_test_rule(
+        "first_name_length",
+        "$.application.individuals.data[*].names[*].name.first",
+        "min_length:2 when $.application.individuals.number == 1",
+        "",
+    );
+    let payload = |number: i64| json!({
+        "application": {
+            "individuals": {
+                "number": number,
+                "data": [{"names": [{"name": {"first": "A"}}]}]
+            }
+        }
+    });
+    
+    // The condition only applies when the guard holds
+    let (result, errors) = engine.apply_rule(&payload(1), &rule).unwrap();
+    assert!(!result);
+    assert_eq!(errors[0].message, "$.application.individuals.data[0].names[0].name.first must be at least 2 characters long");
+    assert!(engine.apply_rule(&payload(2), &rule).unwrap().0);
+    
+    // Guards work with any condition, including presence checks and bare selectors
+    let rule = create_test_rule("reason_required", "$.reason", "required when $.status != \"approved\"", "");
+    assert!(!engine.apply_rule(&json!({"status": "declined"}), &rule).unwrap().0);
+    assert!(engine.apply_rule(&json!({"status": "approved"}), &rule).unwrap().0);
+    let rule = create_test_rule("card_length", "$.card", "min_length:16 when $.card_type", "");
+    assert!(!engine.apply_rule(&json!({"card": "1234", "card_type": "visa"}), &rule).unwrap().0);
+    assert!(engine.apply_rule(&json!({"card": "1234", "card_type": ""}), &rule).unwrap().0);
+    
+    // The removed special case points at its replacement
+    let rule = create_test_rule("legacy", "$.name", "min_length_when_single", "");
+    let err = engine.apply_rule(&json!({}), &rule).unwrap_err();
+    assert!(err.to_string().contains("min_length:2 when $.application.individuals.number == 1"), "{}", err);
+    let rule = create_test_rule("bad_guard", "$.name", "min_length:2 when $.number ==", "");
+    assert!(engine.apply_rule(&json!({}), &rule).is_err());
+    
+    // " when " only starts a guard when a selector follows it outside quotes and regexes
+    let check = |condition: &str, json: serde_json::Value| {
+        let rule = create_test_rule("when_text", "$.value", condition, "");
+        engine.apply_rule(&json, &rule).unwrap().0
+    };
+    assert!(check("equals:pay when due", json!({"value": "pay when due"})));
+    assert!(!check("equals:pay when due", json!({"value": "pay"})));
+    assert!(check("regex:^a when b$", json!({"value": "a when b"})));
+    assert!(!check("regex:^a when b$", json!({"value": "a"})));
+    assert!(check("expr: value == \"a when $.b\"", json!({"value": "a when $.b"})));
+    assert!(!check("expr: value == \"a when $.b\"", json!({"value": "a"})));
+    
+    // A guard can still follow quoted text or a word with an apostrophe
+    assert!(check("expr: value == 'a when b' when $.strict", json!({"value": "x"})));
+    assert!(!check("expr: value == 'a when b' when $.strict", json!({"value": "x", "strict": true})));
+    assert!(!check("equals:O'Brien when $.strict", json!({"value": "x", "strict": true})));
+    assert!(check("equals:O'Brien when $.strict", json!({"value": "x"})));
+}
+
+#[test]
+fn test_dependency_conditions() {
+    let engine = ValidationEngine::new(RuleRepository::new());
+    let check = |depends_on_selector: &str, depends_on_condition: &str, json: &serde_json::Value| {
+        let mut rule = create_test_rule("dependent", "$.guardian", "required", "");
+        rule.depends_on_selector = depends_on_selector.to_string();
+        rule.depends_on_condition = depends_on_condition.to_string();
+        // The rule fails only when its dependency is met (the guardian is always missing)
+        !engine.apply_rule(json, &rule).unwrap().0
+    };
+    
+    let json = json!({
+        "age": 15,
+        "country": "GB",
+        "email": null,
+        "children": [{"age": 4}, {"age": 19}]
+    });
+    
+    // Main condition vocabulary
+    assert!(check("$.age", "max_value:17", &json));
+    assert!(!check("$.age", "min_value:18", &json));
+    assert!(check("$.country", "regex:^[A-Z]{2}$", &json));
+    assert!(check("$.country", "in:[GB,IE]", &json));
+    assert!(!check("$.country", "not_in:[GB,IE]", &json));
+    assert!(check("$.email", "is_null", &json));
+    assert!(check("$.age", "equals:15", &json));
+    
+    // Presence checks
+    assert!(check("$.country", "exists", &json));
+    assert!(!check("$.phone", "exists", &json));
+    assert!(check("$.phone", "missing", &json));
+    assert!(!check("$.email", "not_empty", &json));
+    
+    // Quantifiers over multi-valued selections (any is the default)
+    assert!(check("$.children[*].age", "max_value:17", &json));
+    assert!(check("$.children[*].age", "any:max_value:17", &json));
+    assert!(!check("$.children[*].age", "all:max_value:17", &json));
+    assert!(check("$.children[*].age", "all:min_value:1", &json));
+    assert!(!check("$.pets[*].age", "all:min_value:1", &json));
+    
+    // Unknown dependency conditions are rejected instead of silently disabling the rule
+    let mut rule = create_test_rule("dependent", "$.guardian", "required", "");
+    rule.depends_on_selector = "$.age".to_string();
+    rule.depends_on_condition = "greater_than:17".to_string();
+    let err = engine.apply_rule(&json, &rule).unwrap_err();
+    assert!(err.to_string().contains("invalid dependency condition: unknown condition 'greater_than:17'"), "{}", err);
+}
+
+#[test]
+fn test_dependency_expressions() {
+    let engine = ValidationEngine::new(RuleRepository::new());
+    let applies = |expression: &str, json: &serde_json::Value| {
+        let mut rule = create_test_rule("dependent", "$.tax_id", "required", "");
+        rule.depends_on_condition = expression.to_string();
+        // The rule fails only when its dependencies are met (the tax ID is always missing)
+        !engine.apply_rule(json, &rule).unwrap().0
+    };
+    
+    let employed_us = json!({"employment": {"status": "employed"}, "address": {"country": "US"}});
+    let employed_gb = json!({"employment": {"status": "employed"}, "address": {"country": "GB"}, "visa": "T2"});
+    
+    let expression = "$.employment.status equals:employed AND $.address.country equals:US";
+    assert!(applies(expression, &employed_us));
+    assert!(!applies(expression, &employed_gb));
+    
+    let expression = "$.address.country in:[US, CA] OR $.visa exists";
+    assert!(applies(expression, &employed_us));
+    assert!(applies(expression, &employed_gb));
+    assert!(!applies(expression, &json!({"address": {"country": "FR"}})));
+    
+    // NOT binds tighter than AND, which binds tighter than OR; parentheses group
+    let expression = "$.employment.status equals:employed AND NOT ($.address.country equals:US OR $.visa missing)";
+    assert!(!applies(expression, &employed_us));
+    assert!(applies(expression, &employed_gb));
+    let expression = "NOT $.visa exists AND $.address.country regex:^(US|CA)$";
+    assert!(applies(expression, &employed_us));
+    assert!(!applies(expression, &employed_gb));
+    
+    // Selectors end at the first whitespace outside brackets and quotes
+    let expression = "$.jobs[?@.status == 'employed'] exists AND $.address['home country'] equals:US";
+    assert!(applies(expression, &json!({"jobs": [{"status": "employed"}], "address": {"home country": "US"}})));
+    assert!(!applies(expression, &json!({"jobs": [{"status": "retired"}], "address": {"home country": "US"}})));
+    
+    // A condition without a selector that isn't an expression is ignored, as it was
+    // before dependency expressions, so the rule always applies
+    assert!(applies("not_empty", &employed_us));
+    assert!(applies("a equals:1", &json!({})));
+    
+    // Malformed expressions are rejected when the rule is compiled
+    for expression in [
+        "$.a equals:1 AND",
+        "($.a equals:1",
+        "$.a equals:1 AND OR $.b exists",
+        "NOT a equals:1",
+        "$.a",
+        "$.a equals:1 OR $.b frobnicate",
+    ] {
+        let mut rule = create_test_rule("dependent", "$.tax_id", "required", "");
+        rule.depends_on_condition = expression.to_string();
+        let err = engine.apply_rule(&employed_us, &rule).unwrap_err();
+        assert!(err.to_string().contains("invalid dependency expression"), "{}: {}", expression, err);
+    }
+}
+
+#[test]
+fn test_expression_conditions() {
+    use std::sync::Arc;
+    use chrono::{TimeZone, Utc};
+    use crate::dates::FixedClock;
+    
+    let now = Utc.with_ymd_and_hms(2024, 6, 15, 12, 0, 0).unwrap();
+    let engine = ValidationEngine::new(RuleRepository::new()).with_clock(Arc::new(FixedClock(now)));
+    let json = json!({
+        "income": 4000,
+        "loan": {"amount": 200000, "term_years": 25},
+        "applicant": {"name": "  Ann Smith ", "dob": "2000-01-31", "email": "ann@example.com"},
+        "items": [{"amount": 10}, {"amount": 15.5}],
+        "total": 25.5,
+        "tags": ["new", "priority"]
+    });
+    let holds = |expression: &str| {
+        let rule = create_test_rule("expr_rule", "$", &format!("expr:{}", expression), "");
+        engine.apply_rule(&json, &rule).unwrap().0
+    };
+    
+    assert!(holds("$.income * 12 >= $.loan.amount / 5"));
+    assert!(!holds("$.income * 12 >= $.loan.amount / 4"));
+    assert!(holds("sum($.items[*].amount) == $.total"));
+    assert!(holds("len($.items) == 2 && len(trim($.applicant.name)) == 9"));
+    assert!(holds("lower(trim($.applicant.name)) == 'ann smith'"));
+    assert!(holds("ends_with($.applicant.email, \"@example.com\") and not contains($.tags, 'closed')"));
+    assert!(holds("date($.applicant.dob) < now() && days_between($.applicant.dob, now()) > 18 * 365"));
+    assert!(holds("($.loan.term_years > 30 || $.loan.term_years % 5 == 0) && -$.income < 0"));
+    
+    // Runtime errors such as missing fields in arithmetic or division by zero fail the check
+    assert!(!holds("$.missing * 2 > 1"));
+    assert!(!holds("$.income / 0 > 1"));
+    assert!(holds("$.missing == null"));
+    
+    // Wildcard, slice and filter paths are lists however many values they match
+    let one_item = json!({"a": [{"x": 1}], "b": [{"x": 1}, {"x": 2}]});
+    let holds_for = |expression: &str, json: &serde_json::Value| {
+        let rule = create_test_rule("expr_rule", "$", &format!("expr:{}", expression), "");
+        engine.apply_rule(json, &rule).unwrap().0
+    };
+    assert!(holds_for("len($.a[*]) == 1 && len($.b[*]) == 2", &one_item));
+    assert!(holds_for("len($.a[0:1]) == 1 && len($.a[?@.x == 1]) == 1 && len($..x) == 3", &one_item));
+    assert!(holds_for("sum($.a[*].x) == 1 && len($.missing[*]) == 0", &one_item));
+    assert!(holds_for("$.a[0].x == 1 && $['a'][0]['x'] == 1", &one_item));
+    
+    // "value" refers to the selected value
+    let rule = create_test_rule("item_amounts", "$.items[*].amount", "expr:value > 12", "");
+    let (result, errors) = engine.apply_rule(&json, &rule).unwrap();
+    assert!(!result);
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].path, "$.items[0].amount");
+    assert_eq!(errors[0].message, "$.items[0].amount must satisfy value > 12");
+    
+    // "@" refers to the scope element, as in the rule's other selectors
+    let mut rule = create_test_rule("item_limits", "@.amount", "expr:value <= @.limit && @.amount == value", "");
+    rule.scope = "$.items[*]".to_string();
+    let json = json!({"items": [{"amount": 10, "limit": 12}, {"amount": 15, "limit": 12}]});
+    let (result, errors) = engine.apply_rule(&json, &rule).unwrap();
+    assert!(!result);
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].path, "$.items[1].amount");
+    
+    // Without a scope "@" is the document
+    assert!(holds_for("@.items[0].amount == $.items[0].amount", &json));
+}
+
+#[test]
+fn test_expression_errors_rejected_at_compile_time() {
+    let engine = ValidationEngine::new(RuleRepository::new());
+    let compile_error = |expression: &str| {
+        let rule = create_test_rule("expr_rule", "$", &format!("expr:{}", expression), "");
+        engine.apply_rule(&json!({}), &rule).unwrap_err().to_string()
+    };
+    
+    assert!(compile_error("$.income * 12 >=").contains("unexpected end of expression"));
+    assert!(compile_error("$.a = 1").contains("use '==' for comparison"));
+    assert!(compile_error("frobnicate($.a)").contains("unknown function 'frobnicate'"));
+    assert!(compile_error("len($.a, $.b) > 1").contains("len() takes 1 argument(s) but 2 were given"));
+    assert!(compile_error("'abc' * 2 > 1").contains("'*' cannot be applied to a string"));
+    assert!(compile_error("$.a > 'x' && 1 < 'y'").contains("cannot compare number with string"));
+    assert!(compile_error("$.a + 1").contains("must evaluate to a boolean, not a number"));
+    assert!(compile_error("upper(1) == 'A'").contains("upper() cannot be applied to a number"));
+    assert!(compile_error("$.a[ == 1").contains("unclosed '['"));
+}
+
+#[test]
+fn test_composite_conditions() {
+    let engine = ValidationEngine::new(RuleRepository::new());
+    let check = |condition: &str, value: serde_json::Value| {
+        let rule = create_test_rule("composite", "$.value", condition, "");
+        engine.apply_rule(&json!({"value": value}), &rule).unwrap()
+    };
+    
+    assert!(check("all_of(is_string, min_length:2, max_length:5)", json!("abc")).0);
+    assert!(!check("all_of(is_string, min_length:2, max_length:5)", json!("abcdef")).0);
+    assert!(check("any_of(is_number, regex:^[0-9]+$)", json!("123")).0);
+    assert!(!check("any_of(is_number, regex:^[0-9]+$)", json!("12a")).0);
+    assert!(check("not(in:[N/A, unknown])", json!("Smith")).0);
+    assert!(!check("not(in:[N/A, unknown])", json!("N/A")).0);
+    
+    // one_of needs exactly one sub-condition to pass
+    assert!(check("one_of(max_value:10, min_value:100)", json!(5)).0);
+    assert!(!check("one_of(max_value:10, min_value:5)", json!(7)).0);
+    assert!(!check("one_of(max_value:10, min_value:100)", json!(50)).0);
+    
+    // Nesting and guards compose; commas inside sub-conditions don't split them
+    assert!(check("any_of(all_of(is_string, in:[a,b]), all_of(is_number, min_value:1))", json!(3)).0);
+    assert!(!check("any_of(all_of(is_string, in:[a,b]), all_of(is_number, min_value:1))", json!("c")).0);
+    assert!(check("all_of(min_length:5) when $.value == 'x'", json!("abc")).0);
+    
+    // A failing combination produces a single error
+    let (result, errors) = check("all_of(is_string, min_length:2, regex:^[a-z]+$)", json!("A"));
+    assert!(!result);
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].message, "$.value must satisfy all of is_string, min_length:2, regex:^[a-z]+$ but was A");
+    
+    // A missing value fails all_of when any part requires presence
+    let rule = create_test_rule("composite", "$.missing", "all_of(required, min_length:2)", "");
+    assert!(!engine.apply_rule(&json!({}), &rule).unwrap().0);
+    
+    for condition in ["all_of(is_string, min_lenght:2)", "not(is_string, is_number)", "all_of(is_string,)", "all_of(is_string", "all_of(sum_equals_field:$.total)"] {
+        let rule = create_test_rule("composite", "$.value", condition, "");
+        assert!(engine.apply_rule(&json!({}), &rule).is_err(), "{} should be rejected", condition);
+    }
+}
+
+#[test]
+fn test_if_rule_errors_reported_when_opted_in() {
+    use crate::models::ConditionalLogic;
+    
+    let build_engine = |report_errors: Option<serde_json::Value>| {
+        let mut adult_check = create_test_rule("adult_check", "$.age", "min_value:18", "age");
+        adult_check.logic_type = ConditionalLogic::If;
+        if let Some(report_errors) = report_errors {
+            adult_check.parameters.insert("report_errors".to_string(), report_errors);
+        }
+        let mut guardian = create_test_rule("guardian_required", "$.guardian", "required", "guardian");
+        guardian.logic_type = ConditionalLogic::Else;
+        guardian.parent_rule_id = "adult_check".to_string();
+        
+        let mut repo = RuleRepository::new();
+        repo.add_rule("age".to_string(), adult_check).unwrap();
+        repo.add_rule("guardian".to_string(), guardian).unwrap();
+        ValidationEngine::new(repo)
+    };
+    let minor = json!({"age": 15, "guardian": "Pat"});
+    
+    // By default the "if" rule only picks the branch
+    assert!(build_engine(None).validate(&minor, "DEFAULT", "ALL").unwrap().valid);
+    assert!(build_engine(Some(json!(false))).validate(&minor, "DEFAULT", "ALL").unwrap().valid);
+    
+    // Opting in reports the predicate's failure alongside the branch's errors
+    for flag in [json!(true), json!("true")] {
+        let result = build_engine(Some(flag)).validate(&json!({"age": 15}), "DEFAULT", "ALL").unwrap();
+        let mut failed: Vec<_> = result.errors.iter().map(|e| e.rule_id.as_str()).collect();
+        failed.sort();
+        assert_eq!(failed, vec!["adult_check", "guardian_required"]);
+    }
+}
+
+#[test]
+fn test_switch_rules() {
+    use crate::models::ConditionalLogic;
+    
+    let mut payment_type = create_test_rule("payment_type", "$.payment.type", "required", "payment.type");
+    payment_type.logic_type = ConditionalLogic::Switch;
+    
+    let branch = |id: &str, selector: &str, logic_type: ConditionalLogic, case_value: &str| {
+        let mut rule = create_test_rule(id, selector, "required", "");
+        rule.logic_type = logic_type;
+        rule.parent_rule_id = "payment_type".to_string();
+        rule.case_value = case_value.to_string();
+        rule
+    };
+    
+    let mut repo = RuleRepository::new();
+    repo.add_rule("payment.type".to_string(), payment_type).unwrap();
+    repo.add_rule("".to_string(), branch("card_number", "$.payment.card.number", ConditionalLogic::Case, "card")).unwrap();
+    repo.add_rule("".to_string(), branch("card_cvv", "$.payment.card.cvv", ConditionalLogic::Case, "card")).unwrap();
+    repo.add_rule("".to_string(), branch("bank_iban", "$.payment.bank.iban", ConditionalLogic::Case, "bank")).unwrap();
+    repo.add_rule("".to_string(), branch("wallet_id", "$.payment.wallet.id", ConditionalLogic::Case, "wallet")).unwrap();
+    repo.add_rule("".to_string(), branch("other_note", "$.payment.note", ConditionalLogic::Default, "")).unwrap();
+    let engine = ValidationEngine::new(repo);
+    
+    let failed_rules = |payment: serde_json::Value| {
+        let result = engine.validate(&json!({"payment": payment}), "DEFAULT", "ALL").unwrap();
+        let mut failed: Vec<String> = result.errors.iter().map(|e| e.rule_id.clone()).collect();
+        failed.sort();
+        failed
+    };
+    
+    // Only the rules of the matching case apply
+    assert_eq!(failed_rules(json!({"type": "card"})), vec!["card_cvv", "card_number"]);
+    assert_eq!(failed_rules(json!({"type": "bank"})), vec!["bank_iban"]);
+    assert!(failed_rules(json!({"type": "wallet", "wallet": {"id": "w-1"}})).is_empty());
+    
+    // Unmatched values take the default branch
+    assert_eq!(failed_rules(json!({"type": "voucher"})), vec!["other_note"]);
+    assert!(failed_rules(json!({"type": "voucher", "note": "gift"})).is_empty());
+    
+    // A missing selector value fails the switch rule's own condition and takes the default branch
+    assert_eq!(failed_rules(json!({"note": ""})), vec!["other_note", "payment_type"]);
+}
+
+#[test]
+fn test_else_if_chain() {
+    use crate::models::ConditionalLogic;
+    
+    let mut is_card = create_test_rule("is_card", "$.type", "equals:card", "type");
+    is_card.logic_type = ConditionalLogic::If;
+    
+    let branch = |id: &str, selector: &str, condition: &str, logic_type: ConditionalLogic, parent: &str| {
+        let mut rule = create_test_rule(id, selector, condition, "");
+        rule.logic_type = logic_type;
+        rule.parent_rule_id = parent.to_string();
+        rule
+    };
+    
+    let mut repo = RuleRepository::new();
+    repo.add_rule("type".to_string(), is_card).unwrap();
+    repo.add_rule("".to_string(), branch("card_number", "$.card", "required", ConditionalLogic::Then, "is_card")).unwrap();
+    repo.add_rule("".to_string(), branch("is_bank", "$.type", "equals:bank", ConditionalLogic::ElseIf, "is_card")).unwrap();
+    repo.add_rule("".to_string(), branch("bank_iban", "$.iban", "required", ConditionalLogic::Then, "is_bank")).unwrap();
+    repo.add_rule("".to_string(), branch("other_note", "$.note", "required", ConditionalLogic::Else, "is_bank")).unwrap();
+    let engine = ValidationEngine::new(repo);
+    
+    let failed_rules = |payload: serde_json::Value| {
+        let result = engine.validate(&payload, "DEFAULT", "ALL").unwrap();
+        result.errors.iter().map(|e| e.rule_id.clone()).collect::<Vec<_>>()
+    };
+    
+    // Exactly one link of the chain applies; the "else_if" predicate itself is not reported
+    assert_eq!(failed_rules(json!({"type": "card"})), vec!["card_number"]);
+    assert_eq!(failed_rules(json!({"type": "bank"})), vec!["bank_iban"]);
+    assert_eq!(failed_rules(json!({"type": "voucher"})), vec!["other_note"]);
+}
+
+#[test]
+fn test_scoped_rules_evaluated_per_element() {
+    use crate::models::ConditionalLogic;
+    
+    let scope = "$.application.individuals.data[*]";
+    let mut employed = create_test_rule("employed", "@.employment.status", "equals:employed", "application.individuals");
+    employed.logic_type = ConditionalLogic::If;
+    employed.scope = scope.to_string();
+    let mut employer_name = create_test_rule("employer_name", "@.employer.name", "required", "");
+    employer_name.logic_type = ConditionalLogic::Then;
+    employer_name.parent_rule_id = "employed".to_string();
+    
+    // A scoped standard rule whose dependency is checked against the same element
+    let mut retired_since = create_test_rule("retired_since", "@.retired_since", "is_date:%Y-%m-%d", "application.individuals");
+    retired_since.scope = scope.to_string();
+    retired_since.depends_on_selector = "@.employment.status".to_string();
+    retired_since.depends_on_condition = "equals:retired".to_string();
+    
+    let mut repo = RuleRepository::new();
+    repo.add_rule("application.individuals".to_string(), employed).unwrap();
+    repo.add_rule("".to_string(), employer_name).unwrap();
+    repo.add_rule("application.individuals".to_string(), retired_since).unwrap();
+    let engine = ValidationEngine::new(repo);
+    
+    let json = json!({"application": {"individuals": {"data": [
+        {"employment": {"status": "employed"}, "employer": {"name": "Acme"}},
+        {"employment": {"status": "employed"}},
+        {"employment": {"status": "unemployed"}, "retired_since": "soon"},
+        {"employment": {"status": "retired"}, "retired_since": "last year"},
+        {"name": "no employment recorded"}
+    ]}}});
+    let result = engine.validate(&json, "DEFAULT", "ALL").unwrap();
+    
+    // Only the employed individual without an employer fails, and only the retired
+    // individual's date is checked; one employed person doesn't affect the others, and
+    // an individual without an employment status doesn't take the then branch
+    let mut failures: Vec<_> = result.errors
+        .iter()
+        .map(|e| (e.rule_id.as_str(), e.path.as_str(), e.pointer.as_deref()))
+        .collect();
+    failures.sort();
+    assert_eq!(failures, vec![
+        ("employer_name", "$.application.individuals.data[1].employer.name", Some("/application/individuals/data/1/employer/name")),
+        ("retired_since", "$.application.individuals.data[3].retired_since", Some("/application/individuals/data/3/retired_since")),
+    ]);
+    
+    // No elements in scope means nothing to check
+    let result = engine.validate(&json!({"application": {"individuals": {"data": []}}}), "DEFAULT", "ALL").unwrap();
+    assert!(result.valid);
+}
+
+#[test]
+fn test_relative_selectors_in_conditions() {
+    let engine = ValidationEngine::new(RuleRepository::new());
+    
+    // Cross-field comparisons and guards resolve "@" against the scope element
+    let mut rule = create_test_rule("end_after_start", "@.end", "greater_than_field:@.start when @.kind == \"fixed\"", "");
+    rule.scope = "$.periods[*]".to_string();
+    let json = json!({"periods": [
+        {"kind": "fixed", "start": "2024-01-01", "end": "2024-02-01"},
+        {"kind": "fixed", "start": "2024-03-01", "end": "2024-02-01"},
+        {"kind": "open", "start": "2024-03-01", "end": "2024-02-01"}
+    ]});
+    let (passed, errors) = engine.apply_rule(&json, &rule).unwrap();
+    assert!(!passed);
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].path, "$.periods[1].end");
+    
+    // Invalid scope selectors are rejected when the rule is compiled
+    rule.scope = "$.periods[".to_string();
+    assert!(engine.apply_rule(&json, &rule).is_err());
+}